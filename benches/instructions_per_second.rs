@@ -0,0 +1,82 @@
+use std::time::Instant;
+
+use luy8_cpu::{
+    assambly::assamble,
+    cpu::{
+        memory::{DataBus, DataDriver, ManipulateBits, RAM},
+        CPU,
+    },
+    RAM_BYTES,
+};
+
+const INSTRUCTIONS: usize = 2_000_000;
+
+/// the ram from before it stored bytes, one `bool` per bit
+///
+/// only the memory can be swapped back, the registers stay bytes, so this is
+/// not the whole baseline. to compare against that run the bench at the
+/// commit before the change.
+struct BitMapRam {
+    bits: Vec<bool>,
+}
+impl DataDriver for BitMapRam {
+    fn get_size(&self) -> usize {
+        self.bits.len()
+    }
+    fn write_byte(&mut self, addr: usize, data: u8) {
+        for i in 0..8 {
+            self.bits[addr * 8 + i] = data.get_bit(7 - i);
+        }
+    }
+    fn read_byte(&self, addr: usize) -> u8 {
+        let mut res: u8 = 0;
+        for i in 0..8 {
+            res.set_bit(7 - i, self.bits[addr * 8 + i]);
+        }
+        res
+    }
+}
+
+fn run(name: &str, program: &[u8], ram: Box<dyn DataDriver>) {
+    let mut cpu = CPU::new();
+    let mut data_bus = DataBus::new();
+    data_bus.add_driver(ram);
+    cpu.mount_data_bus(data_bus);
+    for (i, byte) in program.iter().enumerate() {
+        cpu.data_bus.write_byte(i, *byte);
+    }
+
+    let start = Instant::now();
+    for _ in 0..INSTRUCTIONS {
+        cpu.clock().unwrap();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{}: {} instructions in {:.3}s: {:.0} instructions per second",
+        name,
+        INSTRUCTIONS,
+        elapsed.as_secs_f64(),
+        INSTRUCTIONS as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let program = assamble(
+        r#"
+    loop:
+    add ax, 1
+    mov bx, ax
+    push bx
+    pop cx
+    cmp cx, 0
+    jne loop
+    add dx, 1
+    jmp loop
+    "#
+        .to_string(),
+    )
+    .unwrap();
+    run("bytes", &program, Box::new(RAM::new(RAM_BYTES * 8)));
+    let bits = vec![false; RAM_BYTES * 8];
+    run("bitmap", &program, Box::new(BitMapRam { bits }));
+}
@@ -0,0 +1,24 @@
+mov ax, 104
+str ax, 240
+mov ax, 101
+str ax, 240
+mov ax, 108
+str ax, 240
+str ax, 240
+mov ax, 111
+str ax, 240
+mov ax, 32
+str ax, 240
+mov ax, 119
+str ax, 240
+mov ax, 111
+str ax, 240
+mov ax, 114
+str ax, 240
+mov ax, 108
+str ax, 240
+mov ax, 100
+str ax, 240
+mov ax, 10
+str ax, 240
+hlt
@@ -1,291 +1,495 @@
 use crate::{
-    assambly::*,
-    utils::{Enumerate, SetGetBytes},
+    assambly::{directives, *},
+    cpu::INTERRUPT_VECTOR_TABLE,
+    utils::SetGetBytes,
+    MAX_BYTES,
 };
 
-pub fn pass0(words: Vec<String>) -> CodeTable {
-    enum State {
-        Argument1,
-        Argument2,
-        Opcode,
+fn is_name(word: &Word) -> bool {
+    string_is_ident(&word.text) && !word.text.chars().all(|x| SPECIAL_CHARS.contains(&x))
+}
+/// labels and constants can not look like numbers, strings or registers
+fn check_symbol_name(word: &Word) -> Result<(), AssamblyError> {
+    let text = word.text.as_str();
+    let valid = is_name(word)
+        && !text.starts_with(|x: char| x.is_ascii_digit() || x == '"' || x == '\'')
+        && !text.contains(SPECIAL_CHARS)
+        && !REGISTER_NAMES.contains(&text);
+    match valid {
+        true => Ok(()),
+        false => Err(AssamblyError::new(
+            Some(word.span),
+            format!("invalid name for a label or constant: {}", text),
+        )),
     }
-    let mut code_table: CodeTable = CodeTable::new();
-    let mut i = 0;
-    let mut state = State::Opcode;
-    let mut word: &String;
-    loop {
-        word = &words[i];
-        i += 1;
-        match state {
-            State::Argument1 => {
-                // println!("{i}: argument1: {} next word {:?}", word, words.get(i));
-                if i >= words.len() {
-                    break;
-                }
-                if words.get(i) == Some(&",".to_string()) {
-                    state = State::Argument2;
-                    // println!("changed to argument2 with word: {} next_word: {}",word, words[i]);
-                    i += 1;
-                }
-                if word == &"\n".to_string() {
-                    code_table.add_line();
-                    state = State::Opcode;
-                } else if word == &"[".to_string() {
-                    // println!("found address!!!");
-                    word = &words[i];
-                    i += 1;
-                    let mut argument = format!("[{}", word);
-                    if words[i] == "+" {
-                        i += 1;
-                        // println!("found register addres");
-                        argument.push('+');
-                        word = &words[i];
-                        i += 1;
-                        argument += word;
-                    }
-                    word = &words[i];
-                    i += 1;
-                    if word == &"]".to_string() {
-                        argument.push(']');
-                    }
+}
+pub fn is_string(word: &Word) -> bool {
+    word.text.starts_with('"')
+}
+/// instructions that go to their argument, with a value or an addres alike
+const JUMP_NAMES: [&str; 7] = ["jmp", "je", "jz", "jne", "jmr", "jls", "call"];
+/// a jump to a value past a byte, it is encoded with the addres form
+///
+/// values that are not known yet are near, pass1 lays the program out again
+/// once they are.
+fn is_far_jump(name: &Word, operand: &Operand, symbol_table: &SymbolTable) -> bool {
+    let Operand::Value(value) = operand else {
+        return false;
+    };
+    JUMP_NAMES.contains(&name.text.as_str())
+        && value
+            .evaluate(symbol_table)
+            .is_ok_and(|x| !(0..=0xFF).contains(&x))
+}
 
-                    code_table.last_mut().2 = Some(argument);
-                    state = State::Argument2;
-                } else {
-                    if string_is_ident(word) {
-                        code_table.last_mut().2 = Some(word.clone());
-                    }
-                }
+/// split the words into one statement per line
+pub fn pass0(words: Vec<Word>, errors: &mut Vec<AssamblyError>) -> Vec<Statement> {
+    let mut statements = vec![];
+    for mut line in words.split(|x| x.text == "\n") {
+        let mut labels = vec![];
+        while let [label, colon, rest @ ..] = line {
+            if colon.text != ":" {
+                break;
             }
-            State::Argument2 => {
-                // println!("{i}: argument2: {}", word);
-                if i >= words.len() {
-                    break;
-                }
-                if word == &"\n".to_string() {
-                    code_table.add_line();
-                    state = State::Opcode;
-                } else if word == &"[".to_string() {
-                    // println!("found address!!!");
-                    word = &words[i];
-                    i += 1;
-                    let mut argument = format!("[{}", word);
-                    if words[i] == "+" {
-                        i += 1;
-                        // println!("found register addres");
-                        argument.push('+');
-                        word = &words[i];
-                        i += 1;
-                        argument += word;
-                    }
-                    word = &words[i];
-                    i += 1;
-                    if word == &"]".to_string() {
-                        argument.push(']');
-                    }
-
-                    code_table.last_mut().3 = Some(argument);
-                } else {
-                    if string_is_ident(word) {
-                        code_table.last_mut().3 = Some(word.clone());
-                    }
-                }
+            if let Err(err) = check_symbol_name(label) {
+                errors.push(err);
             }
-            State::Opcode => {
-                if i >= words.len() {
-                    break;
-                }
-                let next_word = &words[i];
-
-                if next_word == &":".to_string() {
-                    i += 1;
-                    code_table.last_mut().0 = Some(word.clone());
-                    continue;
-                } else {
-                    if !string_is_ident(word) {
-                        continue;
-                    }
-                    code_table.last_mut().1 = word.clone();
-                    state = State::Argument1;
-                    continue;
-                }
+            labels.push(label.clone());
+            line = rest;
+        }
+        let (name, arguments) = match parse_instruction(line) {
+            Ok(Some((name, arguments))) => (Some(name), arguments),
+            Ok(None) => (None, vec![]),
+            Err(err) => {
+                errors.push(err);
+                (None, vec![])
             }
         };
+        if name.is_some() || !labels.is_empty() {
+            statements.push(Statement {
+                labels,
+                name,
+                arguments,
+            });
+        }
     }
-    return code_table;
+    statements
 }
-pub fn pass1(code_table: CodeTable) -> SymbolTable {
-    let mut current_byte_location: usize = 0;
-    let mut symbol_table: SymbolTable = Dict::new();
-    for line in code_table.0 {
-        if let Some(label) = line.0 {
-            symbol_table.set(label, Some(current_byte_location));
+/// the name and the comma separated arguments, `None` for an empty line
+///
+/// `name = value` becomes `.equ name, value`.
+fn parse_instruction(words: &[Word]) -> Result<Option<(Word, Vec<Argument>)>, AssamblyError> {
+    if let [constant, equals, value @ ..] = words {
+        if equals.text == "=" {
+            let name = Word {
+                text: ".equ".to_string(),
+                span: equals.span,
+            };
+            let constant = parse_argument(std::slice::from_ref(constant))?;
+            let value = parse_argument(value).map_err(|err| match value {
+                [] => AssamblyError::new(Some(equals.span), "missing value".to_string()),
+                _ => err,
+            })?;
+            return Ok(Some((name, vec![constant, value])));
+        }
+    }
+    let Some((name, rest)) = words.split_first() else {
+        return Ok(None);
+    };
+    if !is_name(name) {
+        return Err(AssamblyError::new(
+            Some(name.span),
+            format!("expected an instruction, found: {}", name.text),
+        ));
+    }
+    let mut arguments = vec![];
+    let mut start = 0;
+    for i in 0..=rest.len() {
+        if rest.is_empty() || (i < rest.len() && rest[i].text != ",") {
+            continue;
         }
-        current_byte_location += 1; // add 1 for the instruction
-        if let Some(arg) = line.2 {
-            if arg.starts_with("[") {
-                current_byte_location += 2;
-            } else {
-                current_byte_location += 1;
+        let words = &rest[start..i];
+        start = i + 1;
+        if words.is_empty() {
+            // two commas in a row or one at the end of the line
+            let comma = rest.get(i).unwrap_or(&rest[i - 1]);
+            return Err(AssamblyError::new(
+                Some(comma.span),
+                "missing argument".to_string(),
+            ));
+        }
+        arguments.push(parse_argument(words)?);
+    }
+    Ok(Some((name.clone(), arguments)))
+}
+/// a value, register or string, `[addres]` or `[register + offset]`
+fn parse_argument(words: &[Word]) -> Result<Argument, AssamblyError> {
+    let register = |word: &Word| REGISTER_NAMES.get_index_of(word.text.as_str());
+    let span = words.first().map(|x| x.span).unwrap_or_default();
+    let operand = match words {
+        [word] if is_string(word) => Operand::String(word.clone()),
+        [word] if register(word).is_some() => Operand::Register(register(word).unwrap()),
+        [open, inner @ .., close] if open.text == "[" => {
+            if close.text != "]" {
+                return Err(AssamblyError::new(
+                    Some(open.span.to(close.span)),
+                    "missing ] at the end of the addres".to_string(),
+                ));
             }
-            if !REGISTER_NAMES.contains(&arg.as_str()) && !arg.starts_with("[") {
-                if symbol_table.get(arg.clone()).is_none() && !string_is_number(&arg) {
-                    symbol_table.set(arg, None)
+            match inner {
+                [reg, plus, offset @ ..] if plus.text == "+" && register(reg).is_some() => {
+                    let offset = Expression::parse(offset, plus.span)?;
+                    Operand::RegAddr(register(reg).unwrap(), offset)
                 }
+                _ => Operand::Addres(Expression::parse(inner, open.span)?),
             }
         }
-        if let Some(arg) = line.3 {
-            if arg.starts_with("[") {
-                current_byte_location += 2;
-            } else {
-                current_byte_location += 1;
+        _ => Operand::Value(Expression::parse(words, span)?),
+    };
+    Ok(Argument {
+        words: words.to_vec(),
+        operand,
+    })
+}
+/// where pass1 put a statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub section: Section,
+    pub addres: usize,
+}
+/// how often pass1 lays the program out before it gives up on the addresses settling
+const MAX_LAYOUTS: usize = 16;
+
+/// give every statement an addres and every label and constant a value
+///
+/// where `.data` and `.bss` start depends on how large the sections before them
+/// are, and sizes depend on where statements start because of `.align` and
+/// on labels used before they are defined, so the program is laid out with
+/// the values of the layout before until nothing changes anymore.
+pub fn pass1(
+    statements: &[Statement],
+    errors: &mut Vec<AssamblyError>,
+) -> (SymbolTable, Vec<Placement>) {
+    let mut starts = [0, 0, 0, INTERRUPT_VECTOR_TABLE];
+    let mut previous: SymbolTable = Dict::new();
+    for _ in 0..MAX_LAYOUTS {
+        let mut layout_errors = vec![];
+        let (symbol_table, placements, ends) =
+            lay_out(statements, starts, &previous, &mut layout_errors);
+        let next = [0, ends[0], ends[1], INTERRUPT_VECTOR_TABLE];
+        if next == starts && symbol_table == previous {
+            errors.extend(layout_errors);
+            return (symbol_table, placements);
+        }
+        starts = next;
+        previous = symbol_table;
+    }
+    errors.push(AssamblyError::new(
+        None,
+        "the addresses of the labels do not settle".to_string(),
+    ));
+    let (symbol_table, placements, _) = lay_out(statements, starts, &previous, errors);
+    (symbol_table, placements)
+}
+/// place the statements with the sections starting at `starts`, also returns
+/// where each section ended
+///
+/// symbols that are not defined yet have their value from `previous`.
+fn lay_out(
+    statements: &[Statement],
+    starts: [usize; 4],
+    previous: &SymbolTable,
+    errors: &mut Vec<AssamblyError>,
+) -> (SymbolTable, Vec<Placement>, [usize; 4]) {
+    let mut counters = starts;
+    let mut section = Section::Text;
+    let mut symbol_table: SymbolTable = Dict::new();
+    let mut known = previous.clone();
+    let mut placements = vec![];
+    for statement in statements {
+        let arguments = &statement.arguments;
+        if let Some(name) = &statement.name {
+            if let Some(new_section) = Section::from_name(&name.text) {
+                section = new_section;
+                if let Err(err) = directives::check_argument_count(name, arguments, 0) {
+                    errors.push(err);
+                }
+            } else if name.text == ".org" {
+                match directives::org_addres(name, arguments, &known) {
+                    Ok(addres) => counters[section as usize] = addres,
+                    Err(err) => errors.push(err),
+                }
             }
-            if !REGISTER_NAMES.contains(&arg.as_str()) && !arg.starts_with("[") {
-                if symbol_table.get(arg.clone()).is_none() && !string_is_number(&arg) {
-                    symbol_table.set(arg, None)
+        }
+        let current_byte_location = counters[section as usize];
+        for label in &statement.labels {
+            define(
+                &mut symbol_table,
+                &mut known,
+                label,
+                current_byte_location as i64,
+                errors,
+            );
+        }
+        if statement.name.as_ref().is_some_and(|x| x.text == ".equ") {
+            match directives::constant(statement.name.as_ref().unwrap(), arguments, &known) {
+                Ok((constant, value)) => {
+                    define(&mut symbol_table, &mut known, constant, value, errors)
                 }
+                Err(err) => errors.push(err),
             }
         }
+        placements.push(Placement {
+            section,
+            addres: current_byte_location,
+        });
+        counters[section as usize] += get_size(statement, current_byte_location, &known);
     }
-    println!("total size should be {}", current_byte_location);
-    return symbol_table;
+    (symbol_table, placements, counters)
 }
-pub fn pass2(code_table: CodeTable, symbol_table: SymbolTable) -> Result<Vec<u8>, AssamblyError> {
-    let mut byte_code: Vec<Option<u8>> = vec![];
-    for (i, line) in code_table.0.enumerate() {
-        let mut opcode = line.1.to_uppercase();
-        if INSTRUCTION_NAMES.contains(&opcode.to_lowercase().as_str()) {
-            byte_code.push(None);
-            let opcode_index = byte_code.len() - 1;
-            // println!("line: {:?}",line);
-            for arg in [line.2.clone(), line.3.clone()] {
-                if let Some(mut arg) = arg {
-                    if arg.starts_with("[") && arg.contains("+") {
-                        opcode += "RA";
-                        arg.remove(0);
-                        arg.remove(arg.len() - 1);
-                        let parts = arg.split("+").collect::<Vec<&str>>();
-                        if parts.len() != 2 {
-                            return Err(AssamblyError::new(
-                                Some(i),
-                                format!("invalid register address: {}", arg),
-                            ));
-                        }
-                        if let Some(reg_id) = REGISTER_NAMES.get_index_of(parts[0]) {
-                            byte_code.push(Some(reg_id as u8))
-                        } else {
-                            return Err(AssamblyError::new(
-                                Some(i),
-                                format!("invalid register: {}", parts[0]),
-                            ));
-                        }
-                        let addres = string_to_usize(parts[1].to_string());
-                        if addres.is_none() {
-                            println!("invalid addres: {}", parts[1]);
-                            return Err(AssamblyError::new(
-                                Some(i),
-                                format!("invalid addres: {}", parts[1]),
-                            ));
-                        }
-                        let addres = addres.unwrap();
-                        byte_code.push(Some(addres as u8));
-                    } else if arg.starts_with("[") {
-                        opcode += "A";
-                        let mut addr_string = arg.clone();
-                        addr_string.remove(0);
-                        addr_string.remove(addr_string.len() - 1);
-                        let addr = string_to_usize(addr_string.clone());
-                        if addr.is_none() {
-                            return Err(AssamblyError::new(
-                                Some(i),
-                                format!("invalid addr: {}", addr_string),
-                            ));
-                        } else if addr.unwrap() > u16::MAX as usize {
-                            return Err(AssamblyError::new(
-                                Some(i),
-                                format!("addres to larger must be valid u16: {}", addr_string),
-                            ));
-                        }
-                        let addr = addr.unwrap();
-                        byte_code.push(Some(addr.get_byte(1) as u8));
-                        byte_code.push(Some(addr.get_byte(0) as u8));
-                    } else {
-                        if let Some(reg_id) = REGISTER_NAMES.get_index_of(&arg) {
-                            opcode += "R";
-                            byte_code.push(Some(reg_id as u8));
-                        } else {
-                            opcode += "V";
-                            if let Some(number) = string_to_usize(arg.clone()) {
-                                byte_code.push(Some(number as u8));
-                            } else {
-                                let label_value = symbol_table.get(arg.clone());
-                                if label_value.is_none() {
-                                    return Err(AssamblyError::new(
-                                        Some(i),
-                                        format!("label is undefind: {}", arg),
-                                    ));
-                                } else {
-                                    let label_value = label_value.unwrap().unwrap();
-                                    byte_code.push(Some(label_value as u8))
-                                }
-                            }
-                        }
-                    }
-                }
+fn define(
+    symbol_table: &mut SymbolTable,
+    known: &mut SymbolTable,
+    word: &Word,
+    value: i64,
+    errors: &mut Vec<AssamblyError>,
+) {
+    if symbol_table.get(word.text.clone()).is_some() {
+        errors.push(AssamblyError::new(
+            Some(word.span),
+            format!("{} is defined twice", word.text),
+        ));
+    }
+    symbol_table.set(word.text.clone(), value);
+    known.set(word.text.clone(), value);
+}
+/// the bytes `pass2` emits for `statement` when it starts at `location`
+///
+/// broken statements get a size anyway, `pass2` reports what is wrong with them.
+pub fn get_size(statement: &Statement, location: usize, symbol_table: &SymbolTable) -> usize {
+    let Some(name) = &statement.name else {
+        return 0;
+    };
+    if name.text.starts_with('.') {
+        return directives::get_size(name, &statement.arguments, location, symbol_table);
+    }
+    let mut size = 1; // add 1 for the instruction
+    for argument in &statement.arguments {
+        match argument.operand {
+            Operand::Addres(_) | Operand::RegAddr(..) => size += 2,
+            _ if is_far_jump(name, &argument.operand, symbol_table) => size += 2,
+            _ => size += 1,
+        }
+    }
+    size
+}
+pub fn pass2(
+    statements: &[Statement],
+    symbol_table: &SymbolTable,
+    placements: &[Placement],
+    errors: &mut Vec<AssamblyError>,
+) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = vec![];
+    // the first statement of each segment, to point at when segments overlap
+    let mut segment_spans = vec![];
+    for (statement, placement) in statements.iter().zip(placements) {
+        let Some(name) = &statement.name else {
+            continue;
+        };
+        let location = placement.addres;
+        let bytes = match name.text.starts_with('.') {
+            true => directives::encode(name, &statement.arguments, location, symbol_table),
+            false => encode_instruction(name, &statement.arguments, symbol_table),
+        };
+        let size = get_size(statement, location, symbol_table);
+        let bytes = match bytes {
+            Ok(bytes) if bytes.len() != size => {
+                errors.push(AssamblyError::new(
+                    Some(name.span),
+                    format!(
+                        "{} emits {} bytes but got room for {}",
+                        name.text,
+                        bytes.len(),
+                        size
+                    ),
+                ));
+                bytes
             }
-
-            if let Some(opcode_id) = get_opcode_id(opcode.clone()) {
-                // println!("found opcode: {}, opcode_id: {}", opcode, opcode_id);
-                byte_code[opcode_index] = Some(opcode_id as u8);
-            } else {
-                return Err(AssamblyError::new(
-                    Some(i),
-                    format!("opcode: {} is invalid", opcode),
+            Ok(bytes) => bytes,
+            Err(err) => {
+                errors.push(err);
+                // keep the following statements at the addresses pass1 gave them
+                vec![0; size]
+            }
+        };
+        if placement.section == Section::Bss {
+            let reserves = matches!(name.text.as_str(), ".fill" | ".align") || bytes.is_empty();
+            if !reserves || bytes.iter().any(|x| *x != 0) {
+                errors.push(AssamblyError::new(
+                    Some(name.span),
+                    "only .fill with 0 and .align can be used in .bss".to_string(),
                 ));
             }
-        } else {
-            return Err(AssamblyError::new(
-                Some(i),
-                format!("invalid instruction: {}", opcode),
-            ));
+            continue;
+        }
+        if bytes.is_empty() {
+            continue;
+        }
+        match segments.last_mut() {
+            Some(segment)
+                if segment.section == placement.section && segment.get_end() == location =>
+            {
+                segment.bytes.extend(bytes)
+            }
+            _ => {
+                segments.push(Segment {
+                    section: placement.section,
+                    addres: location,
+                    bytes,
+                });
+                segment_spans.push(name.span);
+            }
         }
     }
-    if byte_code.len()
-        != byte_code
+    for (i, segment) in segments.iter().enumerate() {
+        let span = Some(segment_spans[i]);
+        if segment.get_end() > MAX_BYTES {
+            errors.push(AssamblyError::new(
+                span,
+                format!(
+                    "{} at {:#06X} goes past the end of memory",
+                    segment.section.get_name(),
+                    segment.addres
+                ),
+            ));
+        }
+        let overlapping = segments[..i]
             .iter()
-            .filter_map(|x| *x)
-            .collect::<Vec<u8>>()
-            .len()
-    {
-        println!("something is wrong!!!");
+            .find(|x| x.addres < segment.get_end() && segment.addres < x.get_end());
+        if let Some(other) = overlapping {
+            errors.push(AssamblyError::new(
+                span,
+                format!(
+                    "{} at {:#06X}..{:#06X} overlaps {} at {:#06X}..{:#06X}",
+                    segment.section.get_name(),
+                    segment.addres,
+                    segment.get_end(),
+                    other.section.get_name(),
+                    other.addres,
+                    other.get_end()
+                ),
+            ));
+        }
     }
-    return Ok(byte_code.iter().filter_map(|x| *x).collect::<Vec<u8>>());
+    segments
 }
-
-pub fn assamble(mut code: String) -> Result<Vec<u8>, AssamblyError> {
-    code.make_ascii_lowercase();
-    let words: Vec<String> = get_words(code);
-    println!("words: {:?}", words);
-    let code_table: CodeTable = pass0(words);
-    dbg!(&code_table);
-    let symbol_table: SymbolTable = pass1(code_table.clone());
-    let mut unresolved_labels = vec![];
-
-    for key in symbol_table.get_keys() {
-        if symbol_table.get(key.clone()).unwrap().is_none() {
-            println!("unresolved symbol: {}", key);
-            unresolved_labels.push(key)
-        }
+fn encode_instruction(
+    name: &Word,
+    arguments: &[Argument],
+    symbol_table: &SymbolTable,
+) -> Result<Vec<u8>, AssamblyError> {
+    if !INSTRUCTION_NAMES.contains(&name.text.as_str()) {
+        return Err(AssamblyError::new(
+            Some(name.span),
+            format!("invalid instruction: {}", name.text),
+        ));
     }
-    if unresolved_labels.len() != 0 {
+    if let Some(argument) = arguments.get(2) {
         return Err(AssamblyError::new(
-            None,
-            format!("unresolved symbols {:?}", unresolved_labels),
+            Some(argument.span()),
+            format!("{} takes at most 2 arguments", name.text),
         ));
     }
-    println!("{:?}", symbol_table);
-    let byte_code = pass2(code_table, symbol_table);
+    let mut opcode = name.text.to_uppercase();
+    let mut byte_code = vec![0];
+    for argument in arguments {
+        let (suffix, bytes) = encode_argument(name, argument, symbol_table)?;
+        opcode += suffix;
+        byte_code.extend(bytes);
+    }
+    let Some(opcode_id) = get_opcode_id(opcode.clone()) else {
+        let span = arguments.last().map(|x| name.span.to(x.span()));
+        return Err(AssamblyError::new(
+            span.or(Some(name.span)),
+            format!(
+                "{} can not take these arguments, there is no {}",
+                name.text, opcode
+            ),
+        ));
+    };
+    byte_code[0] = opcode_id as u8;
+    Ok(byte_code)
+}
+/// the opcode suffix of the argument and its bytes
+fn encode_argument(
+    name: &Word,
+    argument: &Argument,
+    symbol_table: &SymbolTable,
+) -> Result<(&'static str, Vec<u8>), AssamblyError> {
+    if let Operand::Value(value) = &argument.operand {
+        if let Some(err) = register_typo(value, symbol_table) {
+            return Err(err);
+        }
+    }
+    match &argument.operand {
+        // the offset is added without a sign
+        Operand::RegAddr(reg_id, offset) => match offset.evaluate(symbol_table)? {
+            value @ 0..=0xFF => Ok(("RA", vec![*reg_id as u8, value as u8])),
+            value => Err(AssamblyError::new(
+                Some(offset.get_span()),
+                format!("a register offset must be from 0 to 255: {}", value),
+            )),
+        },
+        Operand::Addres(addr) => {
+            if let Some(err) = register_typo(addr, symbol_table) {
+                return Err(err);
+            }
+            let addr = addr.evaluate_addres(symbol_table)?;
+            Ok(("A", vec![addr.get_byte(1), addr.get_byte(0)]))
+        }
+        // the addres form goes to the same place
+        Operand::Value(target) if is_far_jump(name, &argument.operand, symbol_table) => {
+            let target = target.evaluate_addres(symbol_table)?;
+            Ok(("A", vec![target.get_byte(1), target.get_byte(0)]))
+        }
+        Operand::Register(reg_id) => Ok(("R", vec![*reg_id as u8])),
+        Operand::Value(value) => Ok(("V", vec![value.evaluate_byte(symbol_table)?])),
+        Operand::String(word) => Err(AssamblyError::new(
+            Some(word.span),
+            "a string can only be used as data".to_string(),
+        )),
+    }
+}
+/// `qx` or `[qx + 1]` with no label qx is more likely a mistyped register
+fn register_typo(expression: &Expression, symbol_table: &SymbolTable) -> Option<AssamblyError> {
+    let word = match expression {
+        Expression::Binary(Operator::Add, base, _) => base.as_symbol()?,
+        _ => expression.as_symbol()?,
+    };
+    let text = word.text.as_str();
+    let looks_like_register = REGISTER_NAMES.iter().any(|register| {
+        let different = register.chars().zip(text.chars()).filter(|(a, b)| a != b);
+        register.len() == text.len() && different.count() == 1
+    });
+    match looks_like_register && symbol_table.get(word.text.clone()).is_none() {
+        true => Some(AssamblyError::new(
+            Some(word.span),
+            format!("invalid register: {}", text),
+        )),
+        false => None,
+    }
+}
 
-    // dbg!(&byte_code);
-    return byte_code;
+pub fn assamble(code: String) -> Result<Vec<Segment>, Vec<AssamblyError>> {
+    let mut errors = vec![];
+    let words: Vec<Word> = get_words(code, &mut errors);
+    let statements = pass0(words, &mut errors);
+    let (symbol_table, placements) = pass1(&statements, &mut errors);
+    let segments = pass2(&statements, &symbol_table, &placements, &mut errors);
+
+    // dbg!(&segments);
+    match errors.is_empty() {
+        true => Ok(segments),
+        false => Err(errors),
+    }
 }
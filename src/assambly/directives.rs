@@ -0,0 +1,194 @@
+use crate::{
+    assambly::{parse_string, Argument, AssamblyError, Expression, Operand, SymbolTable, Word},
+    utils::SetGetBytes,
+    MAX_BYTES,
+};
+
+/// the bytes `encode` emits for the directive when it starts at `location`
+pub fn get_size(
+    name: &Word,
+    arguments: &[Argument],
+    location: usize,
+    symbol_table: &SymbolTable,
+) -> usize {
+    match name.text.as_str() {
+        ".db" => arguments.iter().map(data_size).sum(),
+        ".string" => arguments.iter().map(data_size).sum::<usize>() + 1,
+        ".dw" => arguments.len() * 2,
+        ".fill" => fill_count(name, arguments, symbol_table).unwrap_or(0),
+        ".align" => align_padding(name, arguments, location, symbol_table).unwrap_or(0),
+        _ => 0,
+    }
+}
+/// a string takes up its bytes, anything else one byte
+fn data_size(argument: &Argument) -> usize {
+    match &argument.operand {
+        Operand::String(word) => parse_string(word).map(|x| x.len()).unwrap_or(0),
+        _ => 1,
+    }
+}
+
+/// the data of a directive
+///
+/// - `.db 1, label, "text"` bytes and the bytes of strings
+/// - `.dw 1000, label` 16-bit words, high byte first like addresses
+/// - `.string "text"` a string with a 0 at the end
+/// - `.fill count, value` count times value, the value is 0 when left out
+/// - `.align n` zeros up to the next multiple of n
+///
+/// `.org`, `.equ` and the section directives emit nothing, pass1 handles them.
+pub fn encode(
+    name: &Word,
+    arguments: &[Argument],
+    location: usize,
+    symbol_table: &SymbolTable,
+) -> Result<Vec<u8>, AssamblyError> {
+    let mut bytes = vec![];
+    match name.text.as_str() {
+        ".db" | ".string" => {
+            for argument in arguments {
+                match &argument.operand {
+                    Operand::String(word) => bytes.extend(parse_string(word)?),
+                    _ => bytes.push(value(argument)?.evaluate_byte(symbol_table)?),
+                }
+            }
+            if name.text == ".string" {
+                bytes.push(0);
+            }
+        }
+        ".dw" => {
+            for argument in arguments {
+                let word = value(argument)?.evaluate_word(symbol_table)?;
+                bytes.push(word.get_byte(1));
+                bytes.push(word.get_byte(0));
+            }
+        }
+        ".fill" => {
+            check_argument_count(name, arguments, 2)?;
+            let count = fill_count(name, arguments, symbol_table)?;
+            let byte = match arguments.get(1) {
+                Some(argument) => value(argument)?.evaluate_byte(symbol_table)?,
+                None => 0,
+            };
+            bytes = vec![byte; count];
+        }
+        ".align" => {
+            check_argument_count(name, arguments, 1)?;
+            bytes = vec![0; align_padding(name, arguments, location, symbol_table)?];
+        }
+        // pass1 already placed the statements after these and defined the constants
+        ".org" | ".equ" | ".text" | ".data" | ".bss" | ".vectors" => {}
+        _ => {
+            return Err(AssamblyError::new(
+                Some(name.span),
+                format!("unknown directive: {}", name.text),
+            ))
+        }
+    }
+    Ok(bytes)
+}
+pub fn check_argument_count(
+    name: &Word,
+    arguments: &[Argument],
+    max: usize,
+) -> Result<(), AssamblyError> {
+    let msg = match max {
+        0 => format!("{} takes no arguments", name.text),
+        _ => format!("{} takes at most {} arguments", name.text, max),
+    };
+    match arguments.get(max) {
+        Some(argument) => Err(AssamblyError::new(Some(argument.span()), msg)),
+        None => Ok(()),
+    }
+}
+/// data can not be a register, an addres or a string
+fn value(argument: &Argument) -> Result<&Expression, AssamblyError> {
+    match &argument.operand {
+        Operand::Value(expression) => Ok(expression),
+        _ => Err(AssamblyError::new(
+            Some(argument.span()),
+            format!("expected a value, found: {}", argument.text()),
+        )),
+    }
+}
+/// the first argument, labels defined later have their value from the layout before, see `pass1`
+fn first_value<'a>(
+    name: &Word,
+    arguments: &'a [Argument],
+    symbol_table: &SymbolTable,
+) -> Result<(i64, &'a Expression), AssamblyError> {
+    let Some(argument) = arguments.first() else {
+        return Err(AssamblyError::new(
+            Some(name.span),
+            format!("{} needs a value", name.text),
+        ));
+    };
+    let expression = value(argument)?;
+    Ok((expression.evaluate(symbol_table)?, expression))
+}
+/// where `.org` moves the location counter of the current section
+pub fn org_addres(
+    name: &Word,
+    arguments: &[Argument],
+    symbol_table: &SymbolTable,
+) -> Result<usize, AssamblyError> {
+    check_argument_count(name, arguments, 1)?;
+    let (_, expression) = first_value(name, arguments, symbol_table)?;
+    expression.evaluate_addres(symbol_table)
+}
+/// the name and value of `.equ name, value`
+pub fn constant<'a>(
+    name: &Word,
+    arguments: &'a [Argument],
+    symbol_table: &SymbolTable,
+) -> Result<(&'a Word, i64), AssamblyError> {
+    check_argument_count(name, arguments, 2)?;
+    let constant = match arguments.first().map(|x| &x.operand) {
+        Some(Operand::Value(expression)) => expression.as_symbol(),
+        _ => None,
+    };
+    let Some(constant) = constant else {
+        let span = arguments.first().map(|x| x.span()).unwrap_or(name.span);
+        return Err(AssamblyError::new(
+            Some(span),
+            format!("{} needs a name and a value", name.text),
+        ));
+    };
+    let Some(argument) = arguments.get(1) else {
+        return Err(AssamblyError::new(
+            Some(name.span),
+            format!("{} needs a name and a value", name.text),
+        ));
+    };
+    Ok((constant, value(argument)?.evaluate(symbol_table)?))
+}
+fn fill_count(
+    name: &Word,
+    arguments: &[Argument],
+    symbol_table: &SymbolTable,
+) -> Result<usize, AssamblyError> {
+    let (count, expression) = first_value(name, arguments, symbol_table)?;
+    if !(0..=MAX_BYTES as i64).contains(&count) {
+        return Err(AssamblyError::new(
+            Some(expression.get_span()),
+            format!("can not fill {} bytes", count),
+        ));
+    }
+    Ok(count as usize)
+}
+fn align_padding(
+    name: &Word,
+    arguments: &[Argument],
+    location: usize,
+    symbol_table: &SymbolTable,
+) -> Result<usize, AssamblyError> {
+    let (alignment, expression) = first_value(name, arguments, symbol_table)?;
+    if !(1..=MAX_BYTES as i64).contains(&alignment) {
+        return Err(AssamblyError::new(
+            Some(expression.get_span()),
+            format!("can not align to {}", alignment),
+        ));
+    }
+    let alignment = alignment as usize;
+    Ok((alignment - location % alignment) % alignment)
+}
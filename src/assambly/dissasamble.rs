@@ -9,6 +9,17 @@ use crate::{
 
 use super::INSTRUCTION_NAMES;
 
+/// what goes in the comment behind each line of a listing
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ListingOptions {
+    /// the addres of the first byte
+    pub origin: usize,
+    /// the addres of the instruction and for a `call` the return addres it pushes
+    pub addres: bool,
+    /// the bytes of the instruction in hex
+    pub bytes: bool,
+}
+
 pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
     let mut res_table = CodeTable::new();
 
@@ -21,7 +32,6 @@ pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
         // println!("[{}]",i);
         let opcode = OPCODES.get(opcode_id as usize);
         if opcode.is_none() {
-            println!("[{}] invalid opcode: {:X}", i, opcode_id);
             return None;
         }
         let opcode = opcode.unwrap();
@@ -31,7 +41,7 @@ pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
                 let arg2 = opcode.0.arguments.1;
 
                 i += 1;
-                if i >= data.len() {
+                if arg1 != ArgumentType::None && i >= data.len() {
                     break;
                 }
                 let arg1_text: Option<String> = match arg1 {
@@ -40,7 +50,9 @@ pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
                         None
                     }
                     ArgumentType::Value => Some(format!("{}", data[i])),
-                    ArgumentType::Register => Some(REGISTER_NAMES[data[i] as usize].to_string()),
+                    ArgumentType::Register => {
+                        Some(REGISTER_NAMES.get(data[i] as usize)?.to_string())
+                    }
                     ArgumentType::Addres => {
                         let addr_high = data[i];
                         i += 1;
@@ -60,11 +72,15 @@ pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
                             break;
                         }
                         let addr = data[i];
-                        Some(format!("[{} + {}]", REGISTER_NAMES[reg as usize], addr))
+                        Some(format!(
+                            "[{} + {}]",
+                            REGISTER_NAMES.get(reg as usize)?,
+                            addr
+                        ))
                     }
                 };
                 i += 1;
-                if i >= data.len() {
+                if arg2 != ArgumentType::None && i >= data.len() {
                     break;
                 }
                 let arg2_text: Option<String> = match arg2 {
@@ -73,7 +89,9 @@ pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
                         None
                     }
                     ArgumentType::Value => Some(format!("{}", data[i])),
-                    ArgumentType::Register => Some(REGISTER_NAMES[data[i] as usize].to_string()),
+                    ArgumentType::Register => {
+                        Some(REGISTER_NAMES.get(data[i] as usize)?.to_string())
+                    }
                     ArgumentType::Addres => {
                         let addr_high = data[i];
                         i += 1;
@@ -93,12 +111,15 @@ pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
                             break;
                         }
                         let addr = data[i];
-                        Some(format!("[{} + {}]", REGISTER_NAMES[reg as usize], addr))
+                        Some(format!(
+                            "[{} + {}]",
+                            REGISTER_NAMES.get(reg as usize)?,
+                            addr
+                        ))
                     }
                 };
                 res_table.add_line();
                 let current_line = res_table.last_mut();
-                println!("[{}] {}", i, instruction_name);
                 current_line.1 = instruction_name.to_string();
                 current_line.2 = arg1_text;
                 current_line.3 = arg2_text;
@@ -109,3 +130,38 @@ pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
     }
     return Some(res_table);
 }
+
+pub fn dissassamble_listing(data: Vec<u8>, options: ListingOptions) -> String {
+    let mut listing = String::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let size = OPCODES.get(data[pos] as usize).map(|x| x.0.get_size());
+        let text = size
+            .and_then(|size| dissassamble(data.get(pos..pos + size)?.to_vec()))
+            .filter(|code_table| code_table.0.len() > 1)
+            .and_then(|code_table| code_table.line_text(1));
+        // bytes that are no instruction are kept as data
+        let (text, size) = match (text, size) {
+            (Some(text), Some(size)) => (text, size),
+            _ => (format!(".db {:#04X}", data[pos]), 1),
+        };
+        let mut comment = vec![];
+        if options.addres {
+            comment.push(format!("{:04X}:", options.origin + pos));
+        }
+        if options.bytes {
+            let bytes = data[pos..pos + size].iter().map(|x| format!("{:02X}", x));
+            comment.extend(bytes);
+        }
+        if options.addres && text.starts_with("call") {
+            let ret = (options.origin + pos + size) as u16;
+            comment.push(format!("returns to {:04X}", ret));
+        }
+        match comment.is_empty() {
+            true => listing += &format!("{}\n", text),
+            false => listing += &format!("{:24}; {}\n", text, comment.join(" ")),
+        }
+        pos += size;
+    }
+    listing
+}
@@ -0,0 +1,260 @@
+use crate::{
+    assambly::{
+        parse_string, string_to_usize, AssamblyError, Span, SymbolTable, Word, SPECIAL_CHARS,
+    },
+    cpu::REGISTER_NAMES,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+}
+/// the binary operators from the weakest to the strongest binding, like in c
+const LEVELS: [&[(&str, Operator)]; 6] = [
+    &[("|", Operator::Or)],
+    &[("^", Operator::Xor)],
+    &[("&", Operator::And)],
+    &[("<<", Operator::Shl), (">>", Operator::Shr)],
+    &[("+", Operator::Add), ("-", Operator::Sub)],
+    &[
+        ("*", Operator::Mul),
+        ("/", Operator::Div),
+        ("%", Operator::Rem),
+    ],
+];
+
+/// a value that is known once every label has an addres
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(i64, Span),
+    /// a label or a constant
+    Symbol(Word),
+    Negate(Box<Expression>, Span),
+    Not(Box<Expression>, Span),
+    /// `hi(x)`, bits 8 to 15
+    High(Box<Expression>, Span),
+    /// `lo(x)`, bits 0 to 7
+    Low(Box<Expression>, Span),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+impl Expression {
+    /// `at` is where to point when `words` is empty
+    pub fn parse(words: &[Word], at: Span) -> Result<Expression, AssamblyError> {
+        let mut parser = Parser {
+            words,
+            pos: 0,
+            end: words.last().map(|x| x.span).unwrap_or(at),
+        };
+        let expression = parser.binary(0)?;
+        match parser.peek() {
+            Some(word) => Err(AssamblyError::new(
+                Some(word.span),
+                format!("unexpected {} in expression", word.text),
+            )),
+            None => Ok(expression),
+        }
+    }
+    pub fn get_span(&self) -> Span {
+        match self {
+            Expression::Number(_, span)
+            | Expression::Negate(_, span)
+            | Expression::Not(_, span)
+            | Expression::High(_, span)
+            | Expression::Low(_, span) => *span,
+            Expression::Symbol(word) => word.span,
+            Expression::Binary(_, left, right) => left.get_span().to(right.get_span()),
+        }
+    }
+    /// the word when the expression is nothing but a symbol
+    pub fn as_symbol(&self) -> Option<&Word> {
+        match self {
+            Expression::Symbol(word) => Some(word),
+            _ => None,
+        }
+    }
+    pub fn evaluate(&self, symbol_table: &SymbolTable) -> Result<i64, AssamblyError> {
+        let value = match self {
+            Expression::Number(number, _) => *number,
+            Expression::Symbol(word) => match symbol_table.get(word.text.clone()) {
+                Some(value) => value,
+                None => {
+                    return Err(AssamblyError::new(
+                        Some(word.span),
+                        format!("undefined label: {}", word.text),
+                    ))
+                }
+            },
+            Expression::Negate(inner, _) => inner.evaluate(symbol_table)?.wrapping_neg(),
+            Expression::Not(inner, _) => !inner.evaluate(symbol_table)?,
+            Expression::High(inner, _) => (inner.evaluate(symbol_table)? >> 8) & 0xFF,
+            Expression::Low(inner, _) => inner.evaluate(symbol_table)? & 0xFF,
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(symbol_table)?;
+                let right = right.evaluate(symbol_table)?;
+                let value = match operator {
+                    Operator::Add => left.checked_add(right),
+                    Operator::Sub => left.checked_sub(right),
+                    Operator::Mul => left.checked_mul(right),
+                    Operator::Div => left.checked_div(right),
+                    Operator::Rem => left.checked_rem(right),
+                    Operator::Shl => u32::try_from(right).ok().and_then(|x| left.checked_shl(x)),
+                    Operator::Shr => u32::try_from(right).ok().and_then(|x| left.checked_shr(x)),
+                    Operator::And => Some(left & right),
+                    Operator::Or => Some(left | right),
+                    Operator::Xor => Some(left ^ right),
+                };
+                let msg = match (operator, right) {
+                    (Operator::Div | Operator::Rem, 0) => "division by zero".to_string(),
+                    _ => "the value of the expression is out of range".to_string(),
+                };
+                value.ok_or(AssamblyError::new(Some(self.get_span()), msg))?
+            }
+        };
+        Ok(value)
+    }
+    /// the value as a byte, negative values in two's complement
+    pub fn evaluate_byte(&self, symbol_table: &SymbolTable) -> Result<u8, AssamblyError> {
+        let value = self.evaluate(symbol_table)?;
+        if !(-0x80..=0xFF).contains(&value) {
+            return Err(AssamblyError::new(
+                Some(self.get_span()),
+                format!("value does not fit in a byte: {}", value),
+            ));
+        }
+        Ok(value as u8)
+    }
+    /// the value as 16 bits, negative values in two's complement
+    pub fn evaluate_word(&self, symbol_table: &SymbolTable) -> Result<u16, AssamblyError> {
+        let value = self.evaluate(symbol_table)?;
+        if !(-0x8000..=0xFFFF).contains(&value) {
+            return Err(AssamblyError::new(
+                Some(self.get_span()),
+                format!("value does not fit in 16 bits: {}", value),
+            ));
+        }
+        Ok(value as u16)
+    }
+    pub fn evaluate_addres(&self, symbol_table: &SymbolTable) -> Result<usize, AssamblyError> {
+        let value = self.evaluate(symbol_table)?;
+        if !(0..=0xFFFF).contains(&value) {
+            return Err(AssamblyError::new(
+                Some(self.get_span()),
+                format!("addres must be valid u16: {}", value),
+            ));
+        }
+        Ok(value as usize)
+    }
+}
+
+struct Parser<'a> {
+    words: &'a [Word],
+    pos: usize,
+    /// where a missing value is reported
+    end: Span,
+}
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Word> {
+        self.words.get(self.pos)
+    }
+    fn next(&mut self) -> Result<&'a Word, AssamblyError> {
+        let word = self.words.get(self.pos).ok_or(AssamblyError::new(
+            Some(self.end),
+            "missing value".to_string(),
+        ))?;
+        self.pos += 1;
+        Ok(word)
+    }
+    fn close(&mut self, open: &Word) -> Result<&'a Word, AssamblyError> {
+        match self.peek() {
+            Some(word) if word.text == ")" => self.next(),
+            _ => Err(AssamblyError::new(
+                Some(open.span),
+                "missing ) for this (".to_string(),
+            )),
+        }
+    }
+    /// operators of `LEVELS[level]` and the ones binding stronger
+    fn binary(&mut self, level: usize) -> Result<Expression, AssamblyError> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(word) = self.peek() {
+            let Some((_, operator)) = operators.iter().find(|(text, _)| *text == word.text) else {
+                break;
+            };
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expression::Binary(*operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+    fn unary(&mut self) -> Result<Expression, AssamblyError> {
+        let word = self.next()?;
+        let text = word.text.as_str();
+        match text {
+            "-" | "~" => {
+                let inner = self.unary()?;
+                let span = word.span.to(inner.get_span());
+                Ok(match text {
+                    "-" => Expression::Negate(Box::new(inner), span),
+                    _ => Expression::Not(Box::new(inner), span),
+                })
+            }
+            "+" => self.unary(),
+            "(" => {
+                let inner = self.binary(0)?;
+                self.close(word)?;
+                Ok(inner)
+            }
+            "hi" | "lo" if self.peek().is_some_and(|x| x.text == "(") => {
+                let open = self.next()?;
+                let inner = Box::new(self.binary(0)?);
+                let span = word.span.to(self.close(open)?.span);
+                Ok(match text {
+                    "hi" => Expression::High(inner, span),
+                    _ => Expression::Low(inner, span),
+                })
+            }
+            _ if text.starts_with('\'') => {
+                let bytes = parse_string(word)?;
+                match bytes.as_slice() {
+                    [byte] => Ok(Expression::Number(*byte as i64, word.span)),
+                    _ => Err(AssamblyError::new(
+                        Some(word.span),
+                        format!("a character must be one byte: {}", text),
+                    )),
+                }
+            }
+            _ if text.starts_with(|x: char| x.is_ascii_digit()) => {
+                match string_to_usize(word.text.clone()).and_then(|x| i64::try_from(x).ok()) {
+                    Some(number) => Ok(Expression::Number(number, word.span)),
+                    None => Err(AssamblyError::new(
+                        Some(word.span),
+                        format!("invalid number: {}", text),
+                    )),
+                }
+            }
+            _ if REGISTER_NAMES.contains(&text) => Err(AssamblyError::new(
+                Some(word.span),
+                format!("a register can not be used in an expression: {}", text),
+            )),
+            _ if text.starts_with('"') || text.chars().all(|x| SPECIAL_CHARS.contains(&x)) => {
+                Err(AssamblyError::new(
+                    Some(word.span),
+                    format!("expected a value, found: {}", text),
+                ))
+            }
+            _ => Ok(Expression::Symbol(word.clone())),
+        }
+    }
+}
@@ -1,12 +1,18 @@
 mod assamble;
+mod directives;
 mod dissasamble;
+mod expression;
+
+pub use dissasamble::ListingOptions;
+pub use expression::{Expression, Operator};
 
 type Line = (Option<String>, String, Option<String>, Option<String>);
-type SymbolTable = Dict<String, Option<usize>>;
+/// the values of the labels and constants
+type SymbolTable = Dict<String, i64>;
 use crate::cpu::REGISTER_NAMES;
-pub const INSTRUCTION_NAMES: [&str; 21] = [
+pub const INSTRUCTION_NAMES: [&str; 26] = [
     "nop", "add", "sub", "and", "or", "xor", "not", "mov", "str", "pop", "push", "cmp", "jmp",
-    "je", "jz", "jne", "jmr", "jls", "int", "call", "ret",
+    "je", "jz", "jne", "jmr", "jls", "int", "call", "ret", "iret", "sti", "cli", "brk", "hlt",
 ];
 #[derive(Clone)]
 pub struct CodeTable(pub Vec<Line>);
@@ -20,9 +26,22 @@ impl CodeTable {
     pub fn last_mut(&mut self) -> &mut Line {
         self.0.last_mut().unwrap()
     }
+    /// the line at `index` as assambly, like `loop: add ax, [bx + 3]`
+    pub fn line_text(&self, index: usize) -> Option<String> {
+        let (label, name, arg1, arg2) = self.0.get(index)?;
+        let arguments: Vec<&str> = arg1.iter().chain(arg2).map(|x| x.as_str()).collect();
+        let mut text = format!("{} {}", name, arguments.join(", "));
+        if let Some(label) = label {
+            text = format!("{}: {}", label, text);
+        }
+        Some(text.trim_end().to_string())
+    }
 }
 
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    fs,
+};
 
 use crate::cpu::instructions::OPCODES;
 impl Debug for CodeTable {
@@ -75,6 +94,7 @@ impl Debug for CodeTable {
         debug_tuple.finish()
     }
 }
+#[derive(Clone, PartialEq)]
 pub struct Dict<K: PartialEq + PartialOrd + Debug + Clone, V: Clone + Debug> {
     keys: Vec<K>,
     vals: Vec<V>,
@@ -230,56 +250,299 @@ pub fn string_to_usize(string: String) -> Option<usize> {
         }
     }
 }
-pub fn get_words(mut code: String) -> Vec<String> {
-    code = code.to_lowercase();
-    let mut words: Vec<String> = vec![];
+const SPECIAL_CHARS: [char; 18] = [
+    ',', '+', '[', ']', ':', '/', '-', '*', '%', '&', '|', '^', '~', '(', ')', '=', '<', '>',
+];
+
+/// where a word is in the source, `line` and `column` count from 1
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    /// in chars
+    pub length: usize,
+}
+impl Span {
+    /// from the start of `self` to the end of `other`, which is on the same line
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            length: (other.column + other.length).saturating_sub(self.column),
+            ..*self
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub text: String,
+    pub span: Span,
+}
+/// the words of one argument, like `ax` or `[`, `bx`, `+`, `3`, `]`, and what they mean
+#[derive(Debug, Clone, PartialEq)]
+pub struct Argument {
+    pub words: Vec<Word>,
+    pub operand: Operand,
+}
+/// an argument like the `ArgumentType` it becomes, strings are only data
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Value(Expression),
+    /// the index in `REGISTER_NAMES`
+    Register(usize),
+    /// `[expression]`
+    Addres(Expression),
+    /// `[register + expression]`
+    RegAddr(usize, Expression),
+    String(Word),
+}
+impl Argument {
+    /// the argument without white space, like `[bx+3]`
+    pub fn text(&self) -> String {
+        self.words.iter().map(|x| x.text.as_str()).collect()
+    }
+    pub fn span(&self) -> Span {
+        let first = self.words.first().map(|x| x.span).unwrap_or_default();
+        let last = self.words.last().map(|x| x.span).unwrap_or_default();
+        first.to(last)
+    }
+}
+/// an instruction and the labels in front of it, a line with only labels has no name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub labels: Vec<Word>,
+    pub name: Option<Word>,
+    pub arguments: Vec<Argument>,
+}
+
+/// the parts of a program, each with its own location counter
+///
+/// without `.org` `.text` starts at 0, `.data` where `.text` ended, `.bss` where
+/// `.data` ended and `.vectors` at the interrupt vector table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Text,
+    Data,
+    /// only reserves space, nothing of it ends up in the segments
+    Bss,
+    Vectors,
+}
+impl Section {
+    pub const ALL: [Section; 4] = [Section::Text, Section::Data, Section::Bss, Section::Vectors];
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Section::Text => ".text",
+            Section::Data => ".data",
+            Section::Bss => ".bss",
+            Section::Vectors => ".vectors",
+        }
+    }
+    /// the section a directive like `.data` switches to
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.get_name() == name)
+    }
+}
+/// bytes to be loaded at `addres`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub section: Section,
+    pub addres: usize,
+    pub bytes: Vec<u8>,
+}
+impl Segment {
+    /// the addres behind the last byte
+    pub fn get_end(&self) -> usize {
+        self.addres + self.bytes.len()
+    }
+}
+/// the segments as one image from addres 0, the gaps are 0
+pub fn flatten(segments: &[Segment]) -> Vec<u8> {
+    let size = segments.iter().map(|x| x.get_end()).max().unwrap_or(0);
+    let mut image = vec![0; size];
+    for segment in segments {
+        image[segment.addres..segment.get_end()].copy_from_slice(&segment.bytes);
+    }
+    image
+}
+
+fn push_word(words: &mut Vec<Word>, word: &mut String, start: Span) {
+    if !word.is_empty() {
+        let length = word.chars().count();
+        let text = std::mem::take(word);
+        words.push(Word {
+            text,
+            span: Span { length, ..start },
+        });
+    }
+}
+enum Comment {
+    /// `;` or `//` until the end of the line
+    Line,
+    /// `/* */` which can span lines, the span is the opening `/*`
+    Block(Span),
+}
+fn new_line_word(line: usize, column: usize) -> Word {
+    Word {
+        text: "\n".to_string(),
+        span: Span {
+            line,
+            column,
+            length: 1,
+        },
+    }
+}
+/// the words of `code` without comments, every line ends with a `"\n"` word
+///
+/// everything but strings is lowercased.
+pub fn get_words(code: String, errors: &mut Vec<AssamblyError>) -> Vec<Word> {
+    let mut words: Vec<Word> = vec![];
     let mut word: String = String::new();
-    let mut instring = false;
-    for ch in code.chars() {
-        if ch == '"' {
-            if instring {
-                word.push(ch);
-                instring = false;
+    let mut start = Span::default();
+    // the quote of the string or character the current word is in
+    let mut quote = None;
+    let mut comment = None;
+    let (mut line, mut column) = (1, 0);
+    let mut chars = code.chars().peekable();
+    while let Some(ch) = chars.next() {
+        column += 1;
+        match comment {
+            Some(Comment::Line) if ch != '\n' => continue,
+            Some(Comment::Block(_)) => {
+                if ch == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    column += 1;
+                    comment = None;
+                } else if ch == '\n' {
+                    // keep the lines so statements and spans stay where they are
+                    words.push(new_line_word(line, column));
+                    line += 1;
+                    column = 0;
+                }
                 continue;
-            } else {
-                instring = true;
             }
+            _ => {}
+        }
+        if word.is_empty() {
+            start = Span {
+                line,
+                column,
+                length: 0,
+            };
         }
-        if instring {
+        // strings and characters keep their case and escapes, `parse_string` handles those
+        if quote.is_some() || ch == '"' || ch == '\'' {
             word.push(ch);
+            let mut last = ch;
+            if quote.is_none() {
+                quote = Some(ch);
+            } else if quote == Some(ch) {
+                quote = None;
+            } else if let Some(escaped) = chars.next_if(|_| ch == '\\') {
+                word.push(escaped);
+                column += 1;
+                last = escaped;
+            }
+            if last == '\n' {
+                line += 1;
+                column = 0;
+            }
+            continue;
+        }
+        if ch == ';' || (ch == '/' && matches!(chars.peek(), Some('/' | '*'))) {
+            push_word(&mut words, &mut word, start);
+            comment = match chars.peek() {
+                Some('*') if ch == '/' => {
+                    chars.next();
+                    column += 1;
+                    Some(Comment::Block(Span {
+                        line,
+                        column: column - 1,
+                        length: 2,
+                    }))
+                }
+                _ => Some(Comment::Line),
+            };
             continue;
         }
         if ch.is_whitespace() {
-            if !word.is_empty() {
-                words.push(word.clone());
-            }
+            push_word(&mut words, &mut word, start);
             if ch == '\n' {
                 // println!("found new_line");
-                words.push("\n".to_string());
+                comment = None;
+                words.push(new_line_word(line, column));
+                line += 1;
+                column = 0;
             }
-            word = String::new();
             continue;
         }
-        if [',', ';', '+', '[', ']', ':', '/'].contains(&ch) {
-            for special_char in [',', ';', '+', '[', ']', ':', '/'] {
-                if ch == special_char {
-                    if !word.is_empty() {
-                        words.push(word.clone());
-                    }
-                    word = String::new();
-                    words.push(ch.to_string());
-                    break;
-                }
+        if SPECIAL_CHARS.contains(&ch) {
+            push_word(&mut words, &mut word, start);
+            let mut text = ch.to_string();
+            // the shifts `<<` and `>>`
+            if let Some(next) = chars.next_if(|x| *x == ch && (ch == '<' || ch == '>')) {
+                text.push(next);
+                column += 1;
             }
+            let length = text.len();
+            words.push(Word {
+                text,
+                span: Span {
+                    line,
+                    column: column + 1 - length,
+                    length,
+                },
+            });
         } else {
-            word.push(ch);
+            word.push(ch.to_ascii_lowercase());
         }
     }
-    if !word.is_empty() {
-        words.push(word.clone());
+    push_word(&mut words, &mut word, start);
+    if let Some(quote) = quote {
+        let span = words.last().map(|x| Span {
+            length: 1,
+            ..x.span
+        });
+        errors.push(AssamblyError::new(
+            span,
+            format!("missing {} at the end of the string", quote),
+        ));
+    }
+    if let Some(Comment::Block(span)) = comment {
+        errors.push(AssamblyError::new(
+            Some(span),
+            "missing */ at the end of the comment".to_string(),
+        ));
     }
     return words;
 }
+/// the bytes of a quoted string or character word, with `\n`, `\t`, `\r`, `\0`, `\\` and `\"` escapes
+pub fn parse_string(word: &Word) -> Result<Vec<u8>, AssamblyError> {
+    let quote = word.text.chars().next().unwrap_or('"');
+    let text = word.text.strip_prefix(quote).unwrap_or(&word.text);
+    let text = text.strip_suffix(quote).unwrap_or(text);
+    let mut bytes = vec![];
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        let ch = match ch {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(ch @ ('\\' | '"' | '\'')) => ch,
+                other => {
+                    let escape: String = other.into_iter().collect();
+                    return Err(AssamblyError::new(
+                        Some(word.span),
+                        format!("unknown escape in string: \\{}", escape),
+                    ));
+                }
+            },
+            ch => ch,
+        };
+        let mut buffer = [0; 4];
+        bytes.extend(ch.encode_utf8(&mut buffer).as_bytes());
+    }
+    Ok(bytes)
+}
 pub fn get_opcode_id(opcode: String) -> Option<usize> {
     for i in 0..OPCODES.len() {
         if OPCODES[i].1 == opcode.as_str() {
@@ -289,28 +552,117 @@ pub fn get_opcode_id(opcode: String) -> Option<usize> {
     return None;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AssamblyError {
     msg: String,
-    line: Option<usize>,
+    span: Option<Span>,
 }
 impl AssamblyError {
-    pub fn new(line: Option<usize>, msg: String) -> Self {
-        Self { msg, line }
+    pub fn new(span: Option<Span>, msg: String) -> Self {
+        Self { msg, span }
+    }
+    pub fn get_msg(&self) -> &str {
+        &self.msg
+    }
+    pub fn get_span(&self) -> Option<Span> {
+        self.span
     }
 }
 impl Display for AssamblyError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.line {
-            Some(line) => write!(f, "[{}] {}", line, self.msg),
+        match self.span {
+            Some(span) => write!(f, "[{}:{}] {}", span.line, span.column, self.msg),
             None => write!(f, "{}", self.msg),
         }
     }
 }
+/// every error of one run, with the source they point into
+#[derive(Debug, Clone)]
+pub struct AssamblyErrors {
+    pub errors: Vec<AssamblyError>,
+    /// shown in front of the line numbers, `<source>` when unknown
+    pub file: Option<String>,
+    source: Vec<String>,
+}
+impl AssamblyErrors {
+    pub fn new(mut errors: Vec<AssamblyError>, code: &str) -> Self {
+        errors.sort_by_key(|x| x.span.map(|span| (span.line, span.column)));
+        Self {
+            errors,
+            file: None,
+            source: code.lines().map(|x| x.to_string()).collect(),
+        }
+    }
+    pub fn with_file(mut self, file: String) -> Self {
+        self.file = Some(file);
+        self
+    }
+}
+/// one error after the other like
+/// ```text
+/// main.asm:2:5: error: invalid register: qx
+///    2 | mov qx, 1
+///      |     ^^
+/// ```
+impl Display for AssamblyErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file = self.file.as_deref().unwrap_or("<source>");
+        let mut lines = vec![];
+        for error in &self.errors {
+            let Some(span) = error.span else {
+                lines.push(format!("{}: error: {}", file, error.msg));
+                continue;
+            };
+            lines.push(format!(
+                "{}:{}:{}: error: {}",
+                file, span.line, span.column, error.msg
+            ));
+            let line = self
+                .source
+                .get(span.line.saturating_sub(1))
+                .map(|x| x.as_str())
+                .unwrap_or("");
+            // keep tabs so the caret lines up with the source
+            let indent: String = line
+                .chars()
+                .take(span.column.saturating_sub(1))
+                .map(|x| if x == '\t' { '\t' } else { ' ' })
+                .collect();
+            lines.push(format!("{:>4} | {}", span.line, line));
+            lines.push(format!(
+                "{:>4} | {}{}",
+                "",
+                indent,
+                "^".repeat(span.length.max(1))
+            ));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
 
-pub fn assamble(code: String) -> Result<Vec<u8>, AssamblyError> {
-    assamble::assamble(code)
+/// the program as one image from addres 0, see `flatten`
+pub fn assamble(code: String) -> Result<Vec<u8>, AssamblyErrors> {
+    assamble_segments(code).map(|segments| flatten(&segments))
+}
+/// the program as segments with their load addresses
+pub fn assamble_segments(code: String) -> Result<Vec<Segment>, AssamblyErrors> {
+    assamble::assamble(code.clone()).map_err(|errors| AssamblyErrors::new(errors, &code))
+}
+/// like `assamble_segments` but the errors name `file_path`
+pub fn assamble_file(file_path: String) -> Result<Vec<Segment>, AssamblyErrors> {
+    let code = match fs::read_to_string(&file_path) {
+        Ok(code) => code,
+        Err(err) => {
+            let error = AssamblyError::new(None, format!("could not read file: {}", err));
+            return Err(AssamblyErrors::new(vec![error], "").with_file(file_path));
+        }
+    };
+    assamble_segments(code).map_err(|errors| errors.with_file(file_path))
 }
 pub fn dissassamble(data: Vec<u8>) -> Option<CodeTable> {
     dissasamble::dissassamble(data)
 }
+/// the instructions in `data` as assambly source, one per line, other bytes as `.db`
+pub fn dissassamble_listing(data: Vec<u8>, options: ListingOptions) -> String {
+    dissasamble::dissassamble_listing(data, options)
+}
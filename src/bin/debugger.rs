@@ -0,0 +1,308 @@
+use std::{
+    env,
+    io::{self, BufRead, Write},
+};
+
+use luy8_cpu::{
+    cpu::{
+        debugger::{Comparison, Condition, Debugger, StopReason, WatchKind},
+        gdb::GdbStub,
+        Register, REGISTER_NAMES,
+    },
+    devices::{console::Console, console_machine},
+    load_program, MAX_BYTES,
+};
+
+/// instructions kept for `back` and `backto`
+const HISTORY_LENGTH: usize = 10_000;
+
+const HELP: &str = "\
+step|s [n]             execute n instructions
+next|n                 step over a call or int
+finish|out             run until the current function returns
+continue|c             run until a breakpoint, watchpoint, fault or halt
+break|b [addr] [if <reg> <op> <value>]
+                       break at addr and/or when the condition holds
+watch|w [r|w|rw] <addr> [length]
+                       stop after an instruction accessed the range
+delete|del <id>        remove a breakpoint or watchpoint
+info                   list the breakpoints and watchpoints
+regs|r                 print the registers
+x <addr> [length]      hexdump memory
+dis|d [addr] [count]   disassemble around addr, the pc by default
+set <reg> <value>      write a register
+poke <addr> <byte>...  write memory
+back [n]               undo n instructions
+backto <addr>          undo until the last write to addr
+quit|q                 exit
+numbers are decimal or hex with 0x, an empty line repeats the last command";
+
+/// `debugger <file> [--gdb <port>]`, with `--gdb` gdb drives the session instead of the prompt
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (file_path, gdb_port) = match args.as_slice() {
+        [file_path] => (file_path.clone(), None),
+        [file_path, option, port] if option == "--gdb" => (file_path.clone(), Some(port)),
+        _ => {
+            println!("usage: debugger <file.bin|file.asm> [--gdb <port>]");
+            return;
+        }
+    };
+    let console = Console::new(Box::new(io::stdout()));
+    let cpu = load_program(file_path).and_then(|program| console_machine(&program, console));
+    let mut cpu = match cpu {
+        Ok(cpu) => cpu,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    cpu.enable_history(HISTORY_LENGTH);
+    let mut debugger = Debugger::new(cpu);
+    if let Some(port) = gdb_port {
+        let addres = format!("127.0.0.1:{}", port);
+        println!("waiting for gdb on {}", addres);
+        if let Err(err) = GdbStub::listen(debugger, &addres) {
+            println!("{}", err);
+        }
+        return;
+    }
+    print_location(&debugger);
+    let mut last_command = String::new();
+    let stdin = io::stdin();
+    loop {
+        print!("(luy8) ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let line = match line.trim() {
+            "" => last_command.clone(),
+            line => line.to_string(),
+        };
+        match command(&mut debugger, &line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => println!("{}", err),
+        }
+        last_command = line;
+    }
+}
+
+/// run one command line, returns false when the debugger should exit
+fn command(debugger: &mut Debugger, line: &str) -> Result<bool, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((&name, args)) = words.split_first() else {
+        return Ok(true);
+    };
+    match name {
+        "help" | "h" => println!("{}", HELP),
+        "quit" | "q" => return Ok(false),
+        "step" | "s" => {
+            let count = args.first().map(|x| parse_number(x)).unwrap_or(Ok(1))?;
+            let mut reason = StopReason::Step;
+            for _ in 0..count {
+                reason = debugger.step();
+                if reason != StopReason::Step {
+                    break;
+                }
+            }
+            stopped(debugger, reason);
+        }
+        "next" | "n" => {
+            let reason = debugger.step_over();
+            stopped(debugger, reason);
+        }
+        "finish" | "out" => {
+            let reason = debugger.step_out();
+            stopped(debugger, reason);
+        }
+        "continue" | "c" => {
+            let reason = debugger.run();
+            stopped(debugger, reason);
+        }
+        "break" | "b" => {
+            let (pc, condition) = match args.split_first() {
+                Some((&"if", condition)) => (None, Some(parse_condition(condition)?)),
+                Some((pc, [])) => (Some(parse_number(pc)?), None),
+                Some((pc, [if_, condition @ ..])) if *if_ == "if" => {
+                    (Some(parse_number(pc)?), Some(parse_condition(condition)?))
+                }
+                _ => (Some(debugger.cpu.get_pc()), None),
+            };
+            let id = debugger.add_conditional_breakpoint(pc, condition);
+            println!("breakpoint {}", id);
+        }
+        "watch" | "w" => {
+            let (kind, args) = match args.split_first() {
+                Some((&"r", args)) => (WatchKind::Read, args),
+                Some((&"w", args)) => (WatchKind::Write, args),
+                Some((&"rw", args)) => (WatchKind::Access, args),
+                _ => (WatchKind::Write, args),
+            };
+            let addr = parse_number(args.first().ok_or("missing address")?)?;
+            let length = args.get(1).map(|x| parse_number(x)).unwrap_or(Ok(1))?;
+            let end = range_end(addr, length)?;
+            let id = debugger.add_watchpoint(addr..end, kind);
+            println!("watchpoint {}", id);
+        }
+        "delete" | "del" => {
+            let id = parse_number(args.first().ok_or("missing id")?)?;
+            if !debugger.remove(id) {
+                return Err(format!("no breakpoint or watchpoint {}", id));
+            }
+        }
+        "info" => {
+            for (id, breakpoint) in debugger.get_breakpoints() {
+                let pc = breakpoint.pc.map(|x| format!("{:#06X}", x));
+                let condition = breakpoint.condition.as_ref().map(|x| {
+                    let name = x.register.get_name();
+                    format!("if {} {:?} {}", name, x.comparison, x.value)
+                });
+                let text: Vec<String> = pc.into_iter().chain(condition).collect();
+                println!("{}: break {}", id, text.join(" "));
+            }
+            for (id, watchpoint) in debugger.get_watchpoints() {
+                let range = &watchpoint.range;
+                println!(
+                    "{}: watch {:?} {:#06X}..{:#06X}",
+                    id, watchpoint.kind, range.start, range.end
+                );
+            }
+        }
+        "regs" | "r" => print_registers(debugger),
+        "x" => {
+            let addr = parse_number(args.first().ok_or("missing address")?)?;
+            let length = args.get(1).map(|x| parse_number(x)).unwrap_or(Ok(64))?;
+            range_end(addr, length)?;
+            for line in debugger.hexdump(addr, length) {
+                println!("{}", line);
+            }
+        }
+        "dis" | "d" => {
+            let pc = debugger.cpu.get_pc();
+            let addr = args.first().map(|x| parse_number(x)).unwrap_or(Ok(pc))?;
+            let count = args.get(1).map(|x| parse_number(x)).unwrap_or(Ok(8))?;
+            for (addr, text) in debugger.disassemble_around(addr, 3, count) {
+                let marker = if addr == pc { "=>" } else { "  " };
+                println!("{} {:04X}: {}", marker, addr, text);
+            }
+        }
+        "set" => {
+            let [name, value] = args else {
+                return Err("usage: set <reg> <value>".to_string());
+            };
+            let register = parse_register(name)?;
+            debugger
+                .cpu
+                .registers
+                .write_reg(register, parse_number(value)?);
+        }
+        "poke" => {
+            let (addr, bytes) = args.split_first().ok_or("missing address")?;
+            let addr = parse_number(addr)?;
+            range_end(addr, bytes.len())?;
+            let bytes = bytes
+                .iter()
+                .map(|x| match parse_number(x)? {
+                    byte @ 0..=0xFF => Ok(byte as u8),
+                    byte => Err(format!("{} does not fit in a byte", byte)),
+                })
+                .collect::<Result<Vec<u8>, String>>()?;
+            for (i, byte) in bytes.into_iter().enumerate() {
+                debugger.cpu.data_bus.poke_byte(addr + i, byte);
+            }
+        }
+        "back" => {
+            let count = args.first().map(|x| parse_number(x)).unwrap_or(Ok(1))?;
+            for _ in 0..count {
+                if debugger.cpu.step_back().is_none() {
+                    println!("no more history");
+                    break;
+                }
+            }
+            print_location(debugger);
+        }
+        "backto" => {
+            let addr = parse_number(args.first().ok_or("missing address")?)?;
+            if debugger.cpu.run_back_to_write(addr).is_none() {
+                println!("no write to {:#06X} in the history", addr);
+            }
+            print_location(debugger);
+        }
+        _ => return Err(format!("unknown command: {}, try help", name)),
+    }
+    Ok(true)
+}
+
+fn stopped(debugger: &Debugger, reason: StopReason) {
+    if reason != StopReason::Step {
+        println!("{}", reason);
+    }
+    print_location(debugger);
+}
+fn print_location(debugger: &Debugger) {
+    let pc = debugger.cpu.get_pc();
+    match debugger.disassemble(pc) {
+        Some((text, _)) => println!("=> {:04X}: {}", pc, text),
+        None => println!("=> {:04X}: illegal opcode", pc),
+    }
+}
+fn print_registers(debugger: &Debugger) {
+    let registers = &debugger.cpu.registers;
+    for (i, name) in REGISTER_NAMES.iter().enumerate() {
+        let val = registers.read_reg(i.into());
+        println!("{:6}{:#06X} {}", name, val, val);
+    }
+    let flags = [
+        ("eq", registers.get_eq()),
+        ("mr", registers.get_mr()),
+        ("ls", registers.get_ls()),
+        ("zero", registers.get_zero()),
+        ("carry", registers.get_carry()),
+        ("ie", registers.get_interrupt_enable()),
+    ];
+    let set: Vec<&str> = flags.iter().filter(|x| x.1).map(|x| x.0).collect();
+    println!("flags [{}]", set.join(" "));
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    let res = match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    res.map_err(|_| format!("invalid number: {}", text))
+}
+/// the end of `length` bytes from `addr`, they have to fit in memory
+fn range_end(addr: usize, length: usize) -> Result<usize, String> {
+    addr.checked_add(length)
+        .filter(|&end| end <= MAX_BYTES)
+        .ok_or(format!(
+            "{:#06X}+{:#X} reaches past the memory",
+            addr, length
+        ))
+}
+fn parse_register(name: &str) -> Result<Register, String> {
+    Register::from_name(name).ok_or(format!("unknown register: {}", name))
+}
+/// `<reg> <op> <value>`
+fn parse_condition(words: &[&str]) -> Result<Condition, String> {
+    let [register, comparison, value] = words else {
+        return Err("expected <reg> <op> <value>".to_string());
+    };
+    let comparison = match *comparison {
+        "==" => Comparison::Equal,
+        "!=" => Comparison::NotEqual,
+        "<" => Comparison::Less,
+        "<=" => Comparison::LessOrEqual,
+        ">" => Comparison::More,
+        ">=" => Comparison::MoreOrEqual,
+        _ => return Err(format!("unknown comparison: {}", comparison)),
+    };
+    Ok(Condition {
+        register: parse_register(register)?,
+        comparison,
+        value: parse_number(value)?,
+    })
+}
@@ -0,0 +1,121 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+    panic,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use luy8_cpu::{
+    cpu::{
+        debugger::Debugger,
+        tui::{OutputBuffer, Tui},
+    },
+    devices::{console::Console, console_machine},
+    load_program,
+};
+
+/// instructions kept for stepping back and for the recent writes
+const HISTORY_LENGTH: usize = 10_000;
+const FRAME_TIME: Duration = Duration::from_millis(50);
+
+/// switch the terminal between line editing and getting every key right away
+fn set_raw_mode(raw: bool) {
+    let args: &[&str] = match raw {
+        true => &["-icanon", "-echo", "-isig", "min", "1"],
+        false => &["icanon", "echo", "isig"],
+    };
+    let _ = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .status();
+    // alternate screen and hidden cursor while the tui is up
+    print!(
+        "{}",
+        if raw {
+            "\x1b[?1049h\x1b[?25l"
+        } else {
+            "\x1b[?25h\x1b[?1049l"
+        }
+    );
+    let _ = io::stdout().flush();
+}
+/// raw mode until it is dropped or the program panics
+struct RawMode;
+impl RawMode {
+    fn enable() -> Self {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            set_raw_mode(false);
+            hook(info);
+        }));
+        set_raw_mode(true);
+        RawMode
+    }
+}
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        set_raw_mode(false);
+    }
+}
+fn read_keys() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut byte = [0];
+        while let Ok(1) = io::stdin().read(&mut byte) {
+            if sender.send(byte[0]).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn main() {
+    let Some(file_path) = env::args().nth(1) else {
+        println!("usage: tui <file.bin|file.asm>");
+        return;
+    };
+    let console = OutputBuffer::default();
+    let program = load_program(file_path);
+    let machine = Console::new(Box::new(console.clone()));
+    let mut cpu = match program.and_then(|program| console_machine(&program, machine)) {
+        Ok(cpu) => cpu,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    cpu.enable_history(HISTORY_LENGTH);
+    let mut tui = Tui::new(Debugger::new(cpu), console);
+    let keys = read_keys();
+    let _raw_mode = RawMode::enable();
+    loop {
+        print!("{}", tui.render());
+        let _ = io::stdout().flush();
+        let key = match tui.is_running() {
+            true => {
+                let frame = Instant::now();
+                while tui.is_running() && frame.elapsed() < FRAME_TIME {
+                    tui.run_slice();
+                }
+                match keys.try_recv() {
+                    Ok(key) => Some(key),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            }
+            false => match keys.recv() {
+                Ok(key) => Some(key),
+                Err(_) => break,
+            },
+        };
+        if let Some(key) = key {
+            if !tui.key(key) {
+                break;
+            }
+        }
+    }
+}
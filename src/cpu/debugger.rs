@@ -0,0 +1,360 @@
+use std::{collections::BTreeMap, fmt::Display, ops::Range};
+
+use crate::{assambly::dissassamble, MAX_BYTES};
+
+use super::{
+    execution::{Executed, Fault},
+    Register, CPU,
+};
+
+/// the longest instruction, an opcode with two 16-bit arguments
+const MAX_INSTRUCTION_SIZE: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    More,
+    MoreOrEqual,
+}
+impl Comparison {
+    pub fn compare(&self, left: usize, right: usize) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::More => left > right,
+            Comparison::MoreOrEqual => left >= right,
+        }
+    }
+}
+
+/// holds when `register` compares to `value`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: usize,
+}
+impl Condition {
+    pub fn holds(&self, cpu: &CPU) -> bool {
+        let val = cpu.registers.read_reg(self.register.clone());
+        self.comparison.compare(val, self.value)
+    }
+}
+
+/// stops before the instruction at `pc` is executed
+///
+/// without a pc the condition is checked before every instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub pc: Option<usize>,
+    pub condition: Option<Condition>,
+}
+impl Breakpoint {
+    pub fn hits(&self, cpu: &CPU) -> bool {
+        self.pc.is_none_or(|pc| pc == cpu.get_pc())
+            && self.condition.as_ref().is_none_or(|x| x.holds(cpu))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// reads and writes
+    Access,
+}
+
+/// stops after an instruction that accessed an address in `range`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub kind: WatchKind,
+}
+
+/// why the debugger gave control back
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// the requested step is done
+    Step,
+    Breakpoint {
+        id: usize,
+        pc: usize,
+    },
+    /// the instruction at `pc` accessed `addr`, `write` tells how
+    Watchpoint {
+        id: usize,
+        pc: usize,
+        addr: usize,
+        write: bool,
+    },
+    /// halted with interrupts disabled
+    Halted,
+    /// the cycle limit of `Debugger::run_for` was reached
+    Paused,
+    Fault(Fault),
+}
+impl Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "stepped"),
+            StopReason::Breakpoint { id, pc } => write!(f, "[{}] breakpoint {}", pc, id),
+            StopReason::Watchpoint {
+                id,
+                pc,
+                addr,
+                write,
+            } => {
+                let access = if *write { "write" } else { "read" };
+                write!(f, "[{}] watchpoint {}: {} at {:#06X}", pc, id, access, addr)
+            }
+            StopReason::Halted => write!(f, "halted"),
+            StopReason::Paused => write!(f, "paused"),
+            StopReason::Fault(fault) => write!(f, "{}", fault),
+        }
+    }
+}
+
+/// breakpoints, watchpoints and stepping on top of `CPU::clock`
+///
+/// breakpoints and watchpoints share their ids.
+#[derive(Debug)]
+pub struct Debugger {
+    pub cpu: CPU,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    next_id: usize,
+}
+impl Debugger {
+    pub fn new(mut cpu: CPU) -> Self {
+        cpu.record_accesses = true;
+        Self {
+            cpu,
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+    pub fn into_cpu(mut self) -> CPU {
+        self.cpu.record_accesses = false;
+        self.cpu
+    }
+    fn take_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+    /// break at `pc`, returns the id of the breakpoint
+    pub fn add_breakpoint(&mut self, pc: usize) -> usize {
+        self.add_conditional_breakpoint(Some(pc), None)
+    }
+    pub fn add_conditional_breakpoint(
+        &mut self,
+        pc: Option<usize>,
+        condition: Option<Condition>,
+    ) -> usize {
+        let id = self.take_id();
+        self.breakpoints.insert(id, Breakpoint { pc, condition });
+        id
+    }
+    pub fn add_watchpoint(&mut self, range: Range<usize>, kind: WatchKind) -> usize {
+        let id = self.take_id();
+        self.watchpoints.insert(id, Watchpoint { range, kind });
+        id
+    }
+    /// remove the breakpoint or watchpoint with `id`, false if there was none
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.breakpoints.remove(&id).is_some() || self.watchpoints.remove(&id).is_some()
+    }
+    pub fn get_breakpoints(&self) -> &BTreeMap<usize, Breakpoint> {
+        &self.breakpoints
+    }
+    pub fn get_watchpoints(&self) -> &BTreeMap<usize, Watchpoint> {
+        &self.watchpoints
+    }
+
+    /// execute one instruction, breakpoints at the current pc are ignored
+    pub fn step(&mut self) -> StopReason {
+        match self.cpu.clock() {
+            Ok(executed) => self
+                .check_watchpoints(executed.pc)
+                .unwrap_or(StopReason::Step),
+            Err(Fault::Halt { .. }) => StopReason::Halted,
+            Err(fault) => StopReason::Fault(fault),
+        }
+    }
+    /// like `step` but a `call` or `int` runs until it returned
+    pub fn step_over(&mut self) -> StopReason {
+        let pc = self.cpu.get_pc();
+        let opcode = self.cpu.data_bus.peek_byte(pc) as usize;
+        let Some((instruction, name)) = self.cpu.instructions.get(opcode) else {
+            return self.step();
+        };
+        if !name.starts_with("CALL") && !name.starts_with("INT") {
+            return self.step();
+        }
+        let next = (pc as u16).wrapping_add(instruction.get_size() as u16) as usize;
+        let stp = self.cpu.registers.read_reg(Register::STP);
+        self.run_to(usize::MAX, |cpu, _| {
+            cpu.get_pc() == next && cpu.registers.read_reg(Register::STP) >= stp
+        })
+    }
+    /// run until the current function returned
+    ///
+    /// that is the first `ret` or `iret` that pops something from above the
+    /// stack pointer of now, the ones of functions it calls pop from below.
+    pub fn step_out(&mut self) -> StopReason {
+        let stp = self.cpu.registers.read_reg(Register::STP);
+        self.run_to(usize::MAX, |cpu, executed| {
+            matches!(executed.name, "RET" | "IRET") && cpu.registers.read_reg(Register::STP) > stp
+        })
+    }
+    /// run until something stops the program
+    pub fn run(&mut self) -> StopReason {
+        self.run_for(usize::MAX)
+    }
+    /// run until something stops the program or at least `max_cycles` cycles passed
+    pub fn run_for(&mut self, max_cycles: usize) -> StopReason {
+        self.run_to(max_cycles, |_, _| false)
+    }
+    /// run until `done` holds after an instruction, a halt with interrupts
+    /// enabled waits for the interrupt like `CPU::run`
+    fn run_to<F: Fn(&CPU, &Executed) -> bool>(&mut self, max_cycles: usize, done: F) -> StopReason {
+        let start = self.cpu.get_cycles();
+        let mut first = true;
+        while self.cpu.get_cycles() - start < max_cycles {
+            if !first {
+                if let Some(reason) = self.check_breakpoints() {
+                    return reason;
+                }
+            }
+            first = false;
+            let executed = match self.cpu.clock() {
+                Ok(executed) => executed,
+                Err(Fault::Halt { .. }) if self.cpu.is_waiting() => continue,
+                Err(Fault::Halt { .. }) => return StopReason::Halted,
+                Err(fault) => return StopReason::Fault(fault),
+            };
+            if let Some(reason) = self.check_watchpoints(executed.pc) {
+                return reason;
+            }
+            if done(&self.cpu, &executed) {
+                return StopReason::Step;
+            }
+        }
+        StopReason::Paused
+    }
+
+    /// the assambly of the instruction at `addr` and its size, `None` for an illegal opcode
+    pub fn disassemble(&self, addr: usize) -> Option<(String, usize)> {
+        let opcode = self.cpu.data_bus.peek_byte(addr) as usize;
+        let size = self.cpu.instructions.get(opcode)?.0.get_size();
+        let bytes = (addr..addr + size)
+            .map(|x| self.cpu.data_bus.peek_byte(x))
+            .collect();
+        let code_table = dissassamble(bytes)?;
+        let text = code_table.line_text(code_table.0.len() - 1)?;
+        Some((text, size))
+    }
+    /// up to `before` instructions before `addr`, the one at `addr` and `after` more
+    ///
+    /// the instructions before `addr` are guessed by looking for a start from
+    /// which the instructions line up with `addr`, illegal opcodes show up as `db`.
+    pub fn disassemble_around(
+        &self,
+        addr: usize,
+        before: usize,
+        after: usize,
+    ) -> Vec<(usize, String)> {
+        let mut start = addr;
+        for back in (1..=before * MAX_INSTRUCTION_SIZE).rev() {
+            let Some(from) = addr.checked_sub(back) else {
+                continue;
+            };
+            let mut pos = from;
+            let mut count = 0;
+            while pos < addr {
+                match self.disassemble(pos) {
+                    Some((_, size)) => pos += size,
+                    None => break,
+                }
+                count += 1;
+            }
+            if pos == addr && count <= before {
+                start = from;
+                break;
+            }
+        }
+        let mut lines = vec![];
+        let mut pos = start;
+        while pos <= addr || lines.iter().filter(|(x, _)| *x > addr).count() < after {
+            let (text, size) = self
+                .disassemble(pos)
+                .unwrap_or((format!("db {:#04X}", self.cpu.data_bus.peek_byte(pos)), 1));
+            lines.push((pos, text));
+            pos += size;
+        }
+        lines
+    }
+    /// `length` bytes from `start` as lines of 16 hex bytes and their ascii, cut off at the end of memory
+    pub fn hexdump(&self, start: usize, length: usize) -> Vec<String> {
+        let end = start
+            .checked_add(length)
+            .filter(|&end| end <= MAX_BYTES)
+            .unwrap_or(MAX_BYTES);
+        (start..end)
+            .step_by(16)
+            .map(|line| {
+                let bytes: Vec<u8> = (line..(line + 16).min(end))
+                    .map(|x| self.cpu.data_bus.peek_byte(x))
+                    .collect();
+                let hex: Vec<String> = bytes.iter().map(|x| format!("{:02X}", x)).collect();
+                let ascii: String = bytes
+                    .iter()
+                    .map(|x| match x {
+                        0x20..=0x7E => *x as char,
+                        _ => '.',
+                    })
+                    .collect();
+                format!("{:04X}: {:47} |{}|", line, hex.join(" "), ascii)
+            })
+            .collect()
+    }
+    fn check_breakpoints(&self) -> Option<StopReason> {
+        let (id, _) = self
+            .breakpoints
+            .iter()
+            .find(|(_, breakpoint)| breakpoint.hits(&self.cpu))?;
+        Some(StopReason::Breakpoint {
+            id: *id,
+            pc: self.cpu.get_pc(),
+        })
+    }
+    /// the first watched access of the instruction at `pc`
+    fn check_watchpoints(&self, pc: usize) -> Option<StopReason> {
+        let accesses = self.cpu.get_accesses();
+        let reads = accesses.reads.iter().map(|addr| (*addr, false));
+        let writes = accesses.writes.iter().map(|x| (x.addr, true));
+        for (addr, write) in reads.chain(writes) {
+            for (id, watchpoint) in &self.watchpoints {
+                let kind_matches = match watchpoint.kind {
+                    WatchKind::Read => !write,
+                    WatchKind::Write => write,
+                    WatchKind::Access => true,
+                };
+                if kind_matches && watchpoint.range.contains(&addr) {
+                    return Some(StopReason::Watchpoint {
+                        id: *id,
+                        pc,
+                        addr,
+                        write,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
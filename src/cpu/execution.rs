@@ -0,0 +1,67 @@
+use std::fmt::Display;
+
+/// what a single `CPU::clock` did
+#[derive(Debug, Clone, PartialEq)]
+pub struct Executed {
+    /// address the opcode was fetched from
+    pub pc: usize,
+    pub opcode: u8,
+    pub name: &'static str,
+    /// the decoded arguments as they were passed to the handler
+    pub arguments: (usize, usize),
+    pub cycles: usize,
+    /// vector of the interrupt that was entered before this instruction
+    pub interrupt: Option<u8>,
+}
+impl Display for Executed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} {}, {}",
+            self.pc, self.name, self.arguments.0, self.arguments.1
+        )
+    }
+}
+
+/// why `CPU::clock` could not finish an instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    IllegalOpcode {
+        pc: usize,
+        opcode: u8,
+    },
+    /// nothing is mapped at `addr` or it can not be written
+    BusError {
+        pc: usize,
+        addr: usize,
+    },
+    Halt {
+        pc: usize,
+    },
+    Breakpoint {
+        pc: usize,
+    },
+}
+impl Fault {
+    /// address of the instruction that faulted
+    pub fn get_pc(&self) -> usize {
+        match self {
+            Fault::IllegalOpcode { pc, .. }
+            | Fault::BusError { pc, .. }
+            | Fault::Halt { pc }
+            | Fault::Breakpoint { pc } => *pc,
+        }
+    }
+}
+impl Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::IllegalOpcode { pc, opcode } => {
+                write!(f, "[{}] illegal opcode: {:#04X}", pc, opcode)
+            }
+            Fault::BusError { pc, addr } => write!(f, "[{}] bus error at: {:#06X}", pc, addr),
+            Fault::Halt { pc } => write!(f, "[{}] halted", pc),
+            Fault::Breakpoint { pc } => write!(f, "[{}] breakpoint", pc),
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Write},
+    net::TcpListener,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::MAX_BYTES;
+
+use super::{
+    debugger::{Debugger, StopReason, WatchKind},
+    execution::Fault,
+    FLAG_CARRY, FLAG_EQ, FLAG_INTERRUPT_ENABLE, FLAG_LS, FLAG_MR, FLAG_ZERO, STATE_REGISTERS,
+};
+
+/// cycles run between checks for a ctrl-c from gdb while continuing
+const CONTINUE_SLICE: usize = 10_000;
+/// sent by gdb to stop a running target
+const INTERRUPT: u8 = 0x03;
+
+/// a gdb remote serial protocol server for one connection
+///
+/// the registers are sent in `STATE_REGISTERS` order as little endian hex,
+/// breakpoints and watchpoints set by gdb are kept by the debugger.
+pub struct GdbStub {
+    pub debugger: Debugger,
+    input: Receiver<u8>,
+    /// bytes that arrived while the target was running
+    received: VecDeque<u8>,
+    output: Box<dyn Write>,
+    /// `Z` packet type and address to the debugger id
+    points: HashMap<(u8, usize), usize>,
+    no_ack: bool,
+}
+impl GdbStub {
+    /// a stub that reads packets from `input` on its own thread and replies to `output`
+    pub fn new(
+        debugger: Debugger,
+        mut input: Box<dyn Read + Send>,
+        output: Box<dyn Write>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut byte = [0];
+            while let Ok(1) = input.read(&mut byte) {
+                if sender.send(byte[0]).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            debugger,
+            input: receiver,
+            received: VecDeque::new(),
+            output,
+            points: HashMap::new(),
+            no_ack: false,
+        }
+    }
+    /// wait for gdb to connect to `addres` and serve it until it detaches
+    pub fn listen(debugger: Debugger, addres: &str) -> io::Result<Debugger> {
+        let listener = TcpListener::bind(addres)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut stub = GdbStub::new(debugger, Box::new(stream.try_clone()?), Box::new(stream));
+        stub.serve()?;
+        Ok(stub.debugger)
+    }
+    /// handle packets until gdb detaches, kills the target or disconnects
+    pub fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle_packet(&packet) {
+                Some(reply) => self.send(&reply)?,
+                None => {
+                    self.send("OK")?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// the next packet with a valid checksum, `None` once the connection closed
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let Some(byte) = self.next_byte() else {
+                return Ok(None);
+            };
+            if byte != b'$' {
+                continue; // acks and ctrl-c while the target is already stopped
+            }
+            let mut data = vec![];
+            loop {
+                match self.next_byte() {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let checksum: Vec<u8> = (0..2).filter_map(|_| self.next_byte()).collect();
+            let checksum = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|x| u8::from_str_radix(x, 16).ok());
+            let valid = checksum == Some(data.iter().fold(0u8, |sum, x| sum.wrapping_add(*x)));
+            if !self.no_ack {
+                self.output.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).to_string()));
+            }
+        }
+    }
+    fn next_byte(&mut self) -> Option<u8> {
+        self.received.pop_front().or_else(|| self.input.recv().ok())
+    }
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, x| sum.wrapping_add(x));
+        write!(self.output, "${}#{:02x}", data, checksum)?;
+        self.output.flush()
+    }
+    /// the reply to `packet`, `None` when the session ends
+    fn handle_packet(&mut self, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" => {
+                let reason = self.debugger.step();
+                self.stop_reply(reason)
+            }
+            "c" => self.resume(),
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "H" => "OK".to_string(),
+            "D" | "k" => return None,
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=1000;qXfer:features:read+;swbreak+;QStartNoAckMode+".to_string();
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = args.split_once(',') else {
+                return "E01".to_string();
+            };
+            let (Some(offset), Some(length)) = (parse_hex(offset), parse_hex(length)) else {
+                return "E01".to_string();
+            };
+            let Some(end) = offset.checked_add(length) else {
+                return "E01".to_string();
+            };
+            let xml = target_description();
+            let part = xml.get(offset.min(xml.len())..end.min(xml.len()));
+            let part = part.unwrap_or("");
+            let more = if end < xml.len() { "m" } else { "l" };
+            return format!("{}{}", more, part);
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+    fn read_registers(&self) -> String {
+        let registers = &self.debugger.cpu.registers;
+        STATE_REGISTERS
+            .into_iter()
+            .map(|(register, size)| to_hex(registers.read_reg(register), size))
+            .collect()
+    }
+    fn write_registers(&mut self, args: &str) -> String {
+        let mut pos = 0;
+        for (register, size) in STATE_REGISTERS {
+            let Some(val) = args.get(pos..pos + size * 2).and_then(from_hex) else {
+                return "E01".to_string();
+            };
+            self.debugger.cpu.registers.write_reg(register, val);
+            pos += size * 2;
+        }
+        "OK".to_string()
+    }
+    fn read_register(&self, args: &str) -> String {
+        match parse_hex(args).and_then(|i| STATE_REGISTERS.get(i).cloned()) {
+            Some((register, size)) => to_hex(self.debugger.cpu.registers.read_reg(register), size),
+            None => "E01".to_string(),
+        }
+    }
+    fn write_register(&mut self, args: &str) -> String {
+        let Some((index, val)) = args.split_once('=') else {
+            return "E01".to_string();
+        };
+        let register = parse_hex(index).and_then(|i| STATE_REGISTERS.get(i).cloned());
+        match (register, from_hex(val)) {
+            (Some((register, _)), Some(val)) => {
+                self.debugger.cpu.registers.write_reg(register, val);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+    fn read_memory(&self, args: &str) -> String {
+        let Some((addr, length)) = parse_range(args) else {
+            return "E01".to_string();
+        };
+        let data_bus = &self.debugger.cpu.data_bus;
+        (addr..addr + length)
+            .map(|x| format!("{:02x}", data_bus.peek_byte(x)))
+            .collect()
+    }
+    fn write_memory(&mut self, args: &str) -> String {
+        let Some((range, data)) = args.split_once(':') else {
+            return "E01".to_string();
+        };
+        let Some((addr, length)) = parse_range(range) else {
+            return "E01".to_string();
+        };
+        for i in 0..length {
+            let Some(byte) = data.get(i * 2..i * 2 + 2).and_then(parse_hex) else {
+                return "E01".to_string();
+            };
+            self.debugger.cpu.data_bus.poke_byte(addr + i, byte as u8);
+        }
+        "OK".to_string()
+    }
+    /// `Z`/`z` packets: 0 and 1 are breakpoints, 2 write, 3 read and 4 access watchpoints
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next().and_then(|x| x.parse::<u8>().ok());
+        let addr = fields.next().and_then(parse_hex);
+        let length = fields.next().and_then(parse_hex).unwrap_or(1);
+        let (Some(kind), Some(addr)) = (kind, addr) else {
+            return "E01".to_string();
+        };
+        if !insert {
+            if let Some(id) = self.points.remove(&(kind, addr)) {
+                self.debugger.remove(id);
+            }
+            return "OK".to_string();
+        }
+        let Some(end) = addr.checked_add(length).filter(|&end| end <= MAX_BYTES) else {
+            return "E01".to_string();
+        };
+        let id = match kind {
+            0 | 1 => self.debugger.add_breakpoint(addr),
+            2 => self.debugger.add_watchpoint(addr..end, WatchKind::Write),
+            3 => self.debugger.add_watchpoint(addr..end, WatchKind::Read),
+            4 => self.debugger.add_watchpoint(addr..end, WatchKind::Access),
+            _ => return String::new(),
+        };
+        if let Some(old) = self.points.insert((kind, addr), id) {
+            self.debugger.remove(old);
+        }
+        "OK".to_string()
+    }
+    /// continue until the program stops, gdb sends a ctrl-c or disconnects
+    fn resume(&mut self) -> String {
+        loop {
+            let reason = self.debugger.run_for(CONTINUE_SLICE);
+            if reason != StopReason::Paused {
+                return self.stop_reply(reason);
+            }
+            match self.input.try_recv() {
+                Ok(INTERRUPT) | Err(TryRecvError::Disconnected) => return "S02".to_string(),
+                Ok(byte) => self.received.push_back(byte),
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+    }
+    /// a halt is reported as a trap so the state can still be looked at
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Watchpoint {
+                id, addr, write, ..
+            } => {
+                let access = self.debugger.get_watchpoints().get(&id).map(|x| &x.kind);
+                let kind = match (access, write) {
+                    (Some(WatchKind::Access), _) => "awatch",
+                    (_, true) => "watch",
+                    (_, false) => "rwatch",
+                };
+                format!("T05{}:{:x};", kind, addr)
+            }
+            StopReason::Breakpoint { .. } => "T05swbreak:;".to_string(),
+            StopReason::Fault(Fault::IllegalOpcode { .. }) => "S04".to_string(),
+            StopReason::Fault(Fault::BusError { .. }) => "S0b".to_string(),
+            _ => "S05".to_string(),
+        }
+    }
+}
+
+/// the luy8 registers for gdb, in the order of the `g` packet
+pub fn target_description() -> String {
+    let flags = [
+        ("eq", FLAG_EQ),
+        ("mr", FLAG_MR),
+        ("ls", FLAG_LS),
+        ("zero", FLAG_ZERO),
+        ("carry", FLAG_CARRY),
+        ("ie", FLAG_INTERRUPT_ENABLE),
+    ];
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.luy8.cpu\">\n\
+         <flags id=\"luy8_flags\" size=\"1\">\n",
+    );
+    for (name, bit) in flags {
+        xml += &format!(
+            "<field name=\"{}\" start=\"{}\" end=\"{}\"/>\n",
+            name, bit, bit
+        );
+    }
+    xml += "</flags>\n";
+    for (i, (register, size)) in STATE_REGISTERS.into_iter().enumerate() {
+        let name = register.get_name();
+        let kind = match name {
+            "pc" => "code_ptr".to_string(),
+            "stk" | "stp" => "data_ptr".to_string(),
+            "flags" => "luy8_flags".to_string(),
+            _ => format!("uint{}", size * 8),
+        };
+        xml += &format!(
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>\n",
+            name,
+            size * 8,
+            kind,
+            i
+        );
+    }
+    xml + "</feature>\n</target>\n"
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+/// `addr,length` in hex, `None` when it reaches past the end of memory
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (addr, length) = text.split_once(',')?;
+    let (addr, length) = (parse_hex(addr)?, parse_hex(length)?);
+    (addr.checked_add(length)? <= MAX_BYTES).then_some((addr, length))
+}
+/// `val` as `size` little endian bytes in hex
+fn to_hex(val: usize, size: usize) -> String {
+    (0..size)
+        .map(|i| format!("{:02x}", (val >> (i * 8)) & 0xFF))
+        .collect()
+}
+/// the value of little endian bytes in hex
+fn from_hex(text: &str) -> Option<usize> {
+    let bytes = (0..text.len() / 2).map(|i| parse_hex(text.get(i * 2..i * 2 + 2)?));
+    let bytes: Option<Vec<usize>> = bytes.collect();
+    Some(bytes?.iter().rev().fold(0, |val, byte| (val << 8) | byte))
+}
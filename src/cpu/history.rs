@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+
+use super::{memory::MemoryWrite, Register, Registers, CPU};
+
+/// what is needed to take back one `CPU::clock`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoEntry {
+    /// the registers before the instruction, pc is the address of the instruction
+    pub registers: Registers,
+    pub halted: bool,
+    /// in the order they happened, with the bytes they overwrote
+    pub writes: Vec<MemoryWrite>,
+}
+impl UndoEntry {
+    pub fn get_pc(&self) -> usize {
+        self.registers.read_reg(Register::PC)
+    }
+    pub fn wrote(&self, addr: usize) -> bool {
+        self.writes.iter().any(|x| x.addr == addr)
+    }
+}
+
+/// ring buffer of the last `capacity` undo entries, the oldest are dropped first
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    entries: VecDeque<UndoEntry>,
+    capacity: usize,
+}
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+    pub fn push(&mut self, entry: UndoEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+    /// the entry of the most recent instruction
+    pub fn pop(&mut self) -> Option<UndoEntry> {
+        self.entries.pop_back()
+    }
+    /// the entries from the oldest to the most recent
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &UndoEntry> {
+        self.entries.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl CPU {
+    /// record an undo entry for every instruction, keeping the last `capacity`
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+    /// take back the last recorded instruction and return its undo entry
+    ///
+    /// only the registers and the written bytes go back, including writes to
+    /// the interrupt controller, devices and the cycle counter keep what happened.
+    pub fn step_back(&mut self) -> Option<UndoEntry> {
+        let entry = self.history.as_mut()?.pop()?;
+        for write in entry.writes.iter().rev() {
+            self.data_bus.restore_byte(write.addr, write.old);
+        }
+        self.registers = entry.registers.clone();
+        self.halted = entry.halted;
+        Some(entry)
+    }
+    /// step back until the instruction that last wrote `addr` is taken back
+    ///
+    /// returns `None` when the history ran out before such a write was found,
+    /// the cpu is then at the oldest recorded instruction.
+    pub fn run_back_to_write(&mut self, addr: usize) -> Option<UndoEntry> {
+        loop {
+            let entry = self.step_back()?;
+            if entry.wrote(addr) {
+                return Some(entry);
+            }
+        }
+    }
+}
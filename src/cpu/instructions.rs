@@ -10,14 +10,41 @@ pub enum ArgumentType {
     Addres = 3,
     RegAddr = 4,
 }
+impl ArgumentType {
+    /// number of bytes the argument takes up after the opcode
+    pub fn get_size(&self) -> usize {
+        match self {
+            ArgumentType::None => 0,
+            ArgumentType::Value => 1,
+            ArgumentType::Register => 1,
+            ArgumentType::Addres => 2,
+            ArgumentType::RegAddr => 2,
+        }
+    }
+}
+/// cycles it takes to enter an interrupt handler: pushing pc and flags and reading the vector
+pub const INTERRUPT_CYCLES: usize = 5;
 #[derive(Clone)]
 pub struct Instruction {
     pub arguments: (ArgumentType, ArgumentType),
+    /// bus accesses the handler makes besides fetching the instruction
+    pub memory_accesses: usize,
     pub handler: fn(&mut CPU, usize, usize),
 }
+impl Instruction {
+    /// bytes the instruction takes up, the opcode and the argument bytes
+    pub fn get_size(&self) -> usize {
+        1 + self.arguments.0.get_size() + self.arguments.1.get_size()
+    }
+    /// every bus access takes a cycle: the opcode, the argument bytes and the memory accesses
+    pub fn get_cycles(&self) -> usize {
+        self.get_size() + self.memory_accesses
+    }
+}
 impl PartialEq for Instruction {
     fn eq(&self, other: &Self) -> bool {
         self.arguments == other.arguments
+            && self.memory_accesses == other.memory_accesses
             && self.handler as *const usize == other.handler as *const usize
     }
 }
@@ -25,6 +52,7 @@ impl Debug for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Instruction")
             .field("arguments", &self.arguments)
+            .field("memory_accesses", &self.memory_accesses)
             .finish()
     }
 }
@@ -32,9 +60,9 @@ use crate::cpu::*;
 use crate::instruction_set;
 instruction_set!(
 // nop
-instruction!(NOP, None, None, |_cpu, _, _| {})
+instruction!(NOP, None, None, 0, |_cpu, _, _| {})
 // add
-instruction!(ADDRV, Register, Value, |cpu, reg, val| {
+instruction!(ADDRV, Register, Value, 0, |cpu, reg, val| {
     let a = cpu.registers.read_reg(reg.into());
     let b = val;
     let res = (a as u8).wrapping_add(b as u8) as usize;
@@ -46,7 +74,7 @@ instruction!(ADDRV, Register, Value, |cpu, reg, val| {
         res
     );
 })
-instruction!(ADDRR, Register, Register, |cpu, reg1, reg2| {
+instruction!(ADDRR, Register, Register, 0, |cpu, reg1, reg2| {
     let a = cpu.registers.read_reg(reg1.into());
     let b = cpu.registers.read_reg(reg2.into());
     let res = (a as u8).wrapping_add(b as u8) as usize;
@@ -58,7 +86,7 @@ instruction!(ADDRR, Register, Register, |cpu, reg1, reg2| {
         res
     );
 })
-instruction!(ADDRA, Register, Addres, |cpu, reg, addr| {
+instruction!(ADDRA, Register, Addres, 1, |cpu, reg, addr| {
     let a = cpu.registers.read_reg(reg.into());
     let b = cpu.data_bus.read_byte(addr) as usize;
     let res = (a as u8).wrapping_add(b as u8) as usize;
@@ -70,7 +98,7 @@ instruction!(ADDRA, Register, Addres, |cpu, reg, addr| {
         res
     );
 })
-instruction!(ADDRRA, Register, RegAddr, |cpu, reg, reg_addr| {
+instruction!(ADDRRA, Register, RegAddr, 1, |cpu, reg, reg_addr| {
     let a = cpu.registers.read_reg(reg.into());
     let b = cpu.data_bus.read_byte(reg_addr) as usize;
     let res = (a as u8).wrapping_add(b as u8) as usize;
@@ -83,7 +111,7 @@ instruction!(ADDRRA, Register, RegAddr, |cpu, reg, reg_addr| {
     );
 })
 // sub
-instruction!(SUBRV, Register, Value, |cpu, reg, val| {
+instruction!(SUBRV, Register, Value, 0, |cpu, reg, val| {
     let a = cpu.registers.read_reg(reg.into());
     let b = val;
     let res = (a as u8).wrapping_sub(b as u8) as usize;
@@ -95,7 +123,7 @@ instruction!(SUBRV, Register, Value, |cpu, reg, val| {
         res
     );
 })
-instruction!(SUBRR, Register, Register, |cpu, reg1, reg2| {
+instruction!(SUBRR, Register, Register, 0, |cpu, reg1, reg2| {
     let a = cpu.registers.read_reg(reg1.into());
     let b = cpu.registers.read_reg(reg2.into());
     let res = (a as u8).wrapping_sub(b as u8) as usize;
@@ -107,7 +135,7 @@ instruction!(SUBRR, Register, Register, |cpu, reg1, reg2| {
         res
     );
 })
-instruction!(SUBRA, Register, Addres, |cpu, reg, addr| {
+instruction!(SUBRA, Register, Addres, 1, |cpu, reg, addr| {
     let a = cpu.registers.read_reg(reg.into());
     let b = cpu.data_bus.read_byte(addr) as usize;
     let res = (a as u8).wrapping_sub(b as u8) as usize;
@@ -119,7 +147,7 @@ instruction!(SUBRA, Register, Addres, |cpu, reg, addr| {
         res
     );
 })
-instruction!(SUBRRA, Register, RegAddr, |cpu, reg, reg_addr| {
+instruction!(SUBRRA, Register, RegAddr, 1, |cpu, reg, reg_addr| {
     let a = cpu.registers.read_reg(reg.into());
     let b = cpu.data_bus.read_byte(reg_addr) as usize;
     let res = (a as u8).wrapping_sub(b as u8) as usize;
@@ -132,120 +160,120 @@ instruction!(SUBRRA, Register, RegAddr, |cpu, reg, reg_addr| {
     );
 })
 // and
-instruction!(ANDRV, Register, Value, |cpu, reg, val| {
+instruction!(ANDRV, Register, Value, 0, |cpu, reg, val| {
     cpu.registers
         .write_reg(reg.into(), cpu.registers.read_reg(reg.into()) & (val));
 })
-instruction!(ANDRR, Register, Register, |cpu, reg1, reg2| {
+instruction!(ANDRR, Register, Register, 0, |cpu, reg1, reg2| {
     cpu.registers.write_reg(
         reg1.into(),
         cpu.registers.read_reg(reg1.into()) & (cpu.registers.read_reg(reg2.into()) as usize),
     );
 })
-instruction!(ANDRA, Register, Addres, |cpu, reg, addr| {
+instruction!(ANDRA, Register, Addres, 1, |cpu, reg, addr| {
     cpu.registers.write_reg(
         reg.into(),
         cpu.registers.read_reg(reg.into()) & (cpu.data_bus.read_byte(addr) as usize),
     );
 })
-instruction!(ANDRRA, Register, RegAddr, |cpu, reg, reg_addr| {
+instruction!(ANDRRA, Register, RegAddr, 1, |cpu, reg, reg_addr| {
     cpu.registers.write_reg(
         reg.into(),
         cpu.registers.read_reg(reg.into()) & (cpu.data_bus.read_byte(reg_addr) as usize),
     );
 })
 // or
-instruction!(ORRV, Register, Value, |cpu, reg, val| {
+instruction!(ORRV, Register, Value, 0, |cpu, reg, val| {
     cpu.registers
         .write_reg(reg.into(), cpu.registers.read_reg(reg.into()) | (val));
 })
-instruction!(ORRR, Register, Register, |cpu, reg1, reg2| {
+instruction!(ORRR, Register, Register, 0, |cpu, reg1, reg2| {
     cpu.registers.write_reg(
         reg1.into(),
         cpu.registers.read_reg(reg1.into()) | (cpu.registers.read_reg(reg2.into()) as usize),
     );
 })
-instruction!(ORRA, Register, Addres, |cpu, reg, addr| {
+instruction!(ORRA, Register, Addres, 1, |cpu, reg, addr| {
     cpu.registers.write_reg(
         reg.into(),
         cpu.registers.read_reg(reg.into()) | (cpu.data_bus.read_byte(addr) as usize),
     );
 })
-instruction!(ORRRA, Register, RegAddr, |cpu, reg, reg_addr| {
+instruction!(ORRRA, Register, RegAddr, 1, |cpu, reg, reg_addr| {
     cpu.registers.write_reg(
         reg.into(),
         cpu.registers.read_reg(reg.into()) | (cpu.data_bus.read_byte(reg_addr) as usize),
     );
 })
 // xor
-instruction!(XORRV, Register, Value, |cpu, reg, val| {
+instruction!(XORRV, Register, Value, 0, |cpu, reg, val| {
     cpu.registers
         .write_reg(reg.into(), cpu.registers.read_reg(reg.into()) ^ (val));
 })
-instruction!(XORRR, Register, Register, |cpu, reg1, reg2| {
+instruction!(XORRR, Register, Register, 0, |cpu, reg1, reg2| {
     cpu.registers.write_reg(
         reg1.into(),
         cpu.registers.read_reg(reg1.into()) ^ (cpu.registers.read_reg(reg2.into()) as usize),
     );
 })
-instruction!(XORRA, Register, Addres, |cpu, reg, addr| {
+instruction!(XORRA, Register, Addres, 1, |cpu, reg, addr| {
     cpu.registers.write_reg(
         reg.into(),
         cpu.registers.read_reg(reg.into()) ^ (cpu.data_bus.read_byte(addr) as usize),
     );
 })
-instruction!(XORRRA, Register, RegAddr, |cpu, reg, reg_addr| {
+instruction!(XORRRA, Register, RegAddr, 1, |cpu, reg, reg_addr| {
     cpu.registers.write_reg(
         reg.into(),
         cpu.registers.read_reg(reg.into()) ^ (cpu.data_bus.read_byte(reg_addr) as usize),
     );
 })
 // not
-instruction!(NOTR, Register, None, |cpu, reg, _| {
+instruction!(NOTR, Register, None, 0, |cpu, reg, _| {
     cpu.registers
         .write_reg(reg.into(), !cpu.registers.read_reg(reg.into()));
 })
 // mov
-instruction!(MOVRV, Register, Value, |cpu, reg, val| {
+instruction!(MOVRV, Register, Value, 0, |cpu, reg, val| {
     cpu.registers.write_reg(reg.into(), val);
 })
-instruction!(MOVRR, Register, Register, |cpu, reg1, reg2| {
+instruction!(MOVRR, Register, Register, 0, |cpu, reg1, reg2| {
     cpu.registers
         .write_reg(reg1.into(), cpu.registers.read_reg(reg2.into()));
 })
-instruction!(MOVRA, Register, Addres, |cpu, reg, addr| {
+instruction!(MOVRA, Register, Addres, 1, |cpu, reg, addr| {
     cpu.registers
         .write_reg(reg.into(), cpu.data_bus.read_byte(addr) as usize);
 })
-instruction!(MOVRRA, Register, RegAddr, |cpu, reg, reg_addr| {
+instruction!(MOVRRA, Register, RegAddr, 1, |cpu, reg, reg_addr| {
     cpu.registers
         .write_reg(reg.into(), cpu.data_bus.read_byte(reg_addr) as usize);
 })
 // str
-instruction!(STRRV, Register, Value, |cpu, reg, val| {
+instruction!(STRRV, Register, Value, 1, |cpu, reg, val| {
     cpu.data_bus
         .write_byte(val, cpu.registers.read_reg(reg.into()) as u8);
 })
-instruction!(STRRR, Register, Register, |cpu, reg1, reg2| {
+instruction!(STRRR, Register, Register, 1, |cpu, reg1, reg2| {
     cpu.data_bus.write_byte(
         cpu.registers.read_reg(reg1.into()),
         cpu.registers.read_reg(reg2.into()) as u8,
     );
 })
-instruction!(STRRA, Register, Addres, |cpu, reg, addr| {
+instruction!(STRRA, Register, Addres, 2, |cpu, reg, addr| {
     cpu.data_bus.write_byte(
         cpu.data_bus.read_byte(addr) as usize,
         cpu.registers.read_reg(reg.into()) as u8,
     );
 })
-instruction!(STRRRA, Register, RegAddr, |cpu, reg, reg_addr| {
+instruction!(STRRRA, Register, RegAddr, 2, |cpu, reg, reg_addr| {
     cpu.data_bus.write_byte(
         cpu.data_bus.read_byte(reg_addr) as usize,
         cpu.registers.read_reg(reg.into()) as u8,
     );
 })
 // pop
-instruction!(POPR, Register, None, |cpu, reg, _| {
+instruction!(POPR, Register, None, 1, |cpu, reg, _| {
     let stp = cpu.registers.read_reg(Register::STP);
     // dbg!(cpu.data_bus.read_byte(stp));
     cpu.registers
@@ -253,12 +281,12 @@ instruction!(POPR, Register, None, |cpu, reg, _| {
     cpu.registers.write_reg(Register::STP, stp.wrapping_add(1));
 })
 // push
-instruction!(PUSHV, Value, None, |cpu, val, _| {
+instruction!(PUSHV, Value, None, 1, |cpu, val, _| {
     let stp = (cpu.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
     cpu.registers.write_reg(Register::STP, stp); // decrement stp
     cpu.data_bus.write_byte(stp, val as u8); // write at stp
 })
-instruction!(PUSHR, Register, None, |cpu, reg, _| {
+instruction!(PUSHR, Register, None, 1, |cpu, reg, _| {
     let stp = (cpu.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
     cpu.registers.write_reg(Register::STP, stp);
     cpu.data_bus.write_byte(
@@ -266,13 +294,13 @@ instruction!(PUSHR, Register, None, |cpu, reg, _| {
         cpu.registers.read_reg(reg.into()) as u8,
     );
 })
-instruction!(PUSHA, Addres, None, |cpu, addr, _| {
+instruction!(PUSHA, Addres, None, 2, |cpu, addr, _| {
     let stp = (cpu.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
     cpu.registers.write_reg(Register::STP, stp);
     cpu.data_bus
         .write_byte(stp, cpu.data_bus.read_byte(addr));
 })
-instruction!(PUSHRA, RegAddr, None, |cpu, reg_addr, _| {
+instruction!(PUSHRA, RegAddr, None, 2, |cpu, reg_addr, _| {
     let stp = (cpu.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
     cpu.registers.write_reg(Register::STP, stp);
     cpu.data_bus
@@ -280,28 +308,28 @@ instruction!(PUSHRA, RegAddr, None, |cpu, reg_addr, _| {
 
 })
 // cmp
-instruction!(CMPRV, Register, Value, |cpu, reg, val| {
+instruction!(CMPRV, Register, Value, 0, |cpu, reg, val| {
     let a = cpu.registers.read_reg(reg.into());
     let b = val;
     cpu.registers.set_eq(a==b);
     cpu.registers.set_mr(a>b);
     cpu.registers.set_ls(a<b);
 })
-instruction!(CMPRR, Register, Register, |cpu, reg1, reg2| {
+instruction!(CMPRR, Register, Register, 0, |cpu, reg1, reg2| {
     let a = cpu.registers.read_reg(reg1.into());
     let b = cpu.registers.read_reg(reg2.into());
     cpu.registers.set_eq(a==b);
     cpu.registers.set_mr(a>b);
     cpu.registers.set_ls(a<b);
 })
-instruction!(CMPRA, Register, Addres, |cpu, reg, addr| {
+instruction!(CMPRA, Register, Addres, 1, |cpu, reg, addr| {
     let a = cpu.registers.read_reg(reg.into());
     let b = cpu.data_bus.read_byte(addr) as usize;
     cpu.registers.set_eq(a==b);
     cpu.registers.set_mr(a>b);
     cpu.registers.set_ls(a<b);
 })
-instruction!(CMPRRA, Register, RegAddr, |cpu, reg, reg_addr| {
+instruction!(CMPRRA, Register, RegAddr, 1, |cpu, reg, reg_addr| {
     let a = cpu.registers.read_reg(reg.into());
     let b = cpu.data_bus.read_byte(reg_addr) as usize;
     cpu.registers.set_eq(a==b);
@@ -309,199 +337,197 @@ instruction!(CMPRRA, Register, RegAddr, |cpu, reg, reg_addr| {
     cpu.registers.set_ls(a<b);
 })
 // jmp
-instruction!(JMPV, Value, None, |cpu, val, _| {
+instruction!(JMPV, Value, None, 0, |cpu, val, _| {
     cpu.registers.write_reg(Register::PC, val);
 })
-instruction!(JMPR, Register, None, |cpu, reg, _| {
+instruction!(JMPR, Register, None, 0, |cpu, reg, _| {
     cpu.registers.write_reg(Register::PC, cpu.registers.read_reg(reg.into()));
 })
-instruction!(JMPA, Addres, None, |cpu, addr, _| {
+instruction!(JMPA, Addres, None, 0, |cpu, addr, _| {
     cpu.registers.write_reg(Register::PC, addr);
 })
-instruction!(JMPRA, RegAddr, None, |cpu, reg_addr, _| {
+instruction!(JMPRA, RegAddr, None, 0, |cpu, reg_addr, _| {
     cpu.registers.write_reg(Register::PC, reg_addr);
 })
 // je
-instruction!(JEV, Value, None, |cpu, val, _| {
+instruction!(JEV, Value, None, 0, |cpu, val, _| {
     if cpu.registers.get_eq() {
         cpu.registers.write_reg(Register::PC, val);
     }
 })
-instruction!(JER, Register, None, |cpu, reg, _| {
+instruction!(JER, Register, None, 0, |cpu, reg, _| {
     if cpu.registers.get_eq() {
         cpu.registers.write_reg(Register::PC, cpu.registers.read_reg(reg.into()));
     }
 })
-instruction!(JEA, Addres, None, |cpu, addr, _| {
+instruction!(JEA, Addres, None, 0, |cpu, addr, _| {
     if cpu.registers.get_eq() {
         cpu.registers.write_reg(Register::PC, addr);
     }
 })
-instruction!(JERA, RegAddr, None, |cpu, reg_addr, _| {
+instruction!(JERA, RegAddr, None, 0, |cpu, reg_addr, _| {
     if cpu.registers.get_eq() {
         cpu.registers.write_reg(Register::PC, reg_addr);
     }
 })
 // jz
-instruction!(JZV, Value, None, |cpu, val, _| {
+instruction!(JZV, Value, None, 0, |cpu, val, _| {
     if cpu.registers.get_zero() {
         cpu.registers.write_reg(Register::PC, val);
     }
 })
-instruction!(JZR, Register,  None, |cpu, reg, _| {
+instruction!(JZR, Register,  None, 0, |cpu, reg, _| {
     if cpu.registers.get_zero() {
         cpu.registers.write_reg(Register::PC, cpu.registers.read_reg(reg.into()));
     }
 })
-instruction!(JZA, Addres,  None, |cpu, addr, _| {
+instruction!(JZA, Addres,  None, 0, |cpu, addr, _| {
     if cpu.registers.get_zero() {
         cpu.registers.write_reg(Register::PC, addr);
     }
 })
-instruction!(JZRA, RegAddr,  None, |cpu, reg_addr, _| {
+instruction!(JZRA, RegAddr,  None, 0, |cpu, reg_addr, _| {
     if cpu.registers.get_zero() {
         cpu.registers.write_reg(Register::PC, reg_addr);
     }
 })
 // jne
-instruction!(JNEV, Value, None, |cpu, val, _| {
+instruction!(JNEV, Value, None, 0, |cpu, val, _| {
     if !cpu.registers.get_eq() {
         cpu.registers.write_reg(Register::PC, val);
     }
 })
-instruction!(JNER, Register,  None, |cpu, reg, _| {
+instruction!(JNER, Register,  None, 0, |cpu, reg, _| {
     if !cpu.registers.get_eq() {
         cpu.registers.write_reg(Register::PC, cpu.registers.read_reg(reg.into()));
     }
 })
-instruction!(JNEA, Addres,  None, |cpu, addr, _| {
+instruction!(JNEA, Addres,  None, 0, |cpu, addr, _| {
     if !cpu.registers.get_eq() {
         cpu.registers.write_reg(Register::PC, addr);
     }
 })
-instruction!(JNERA, RegAddr,  None, |cpu, reg_addr, _| {
+instruction!(JNERA, RegAddr,  None, 0, |cpu, reg_addr, _| {
     if !cpu.registers.get_eq() {
         cpu.registers.write_reg(Register::PC, reg_addr);
     }
 })
 // jmr
-instruction!(JMRV, Value, None, |cpu, val, _| {
+instruction!(JMRV, Value, None, 0, |cpu, val, _| {
     if cpu.registers.get_mr() {
         cpu.registers.write_reg(Register::PC, val);
     }
 })
-instruction!(JMRR, Register,  None, |cpu, reg, _| {
+instruction!(JMRR, Register,  None, 0, |cpu, reg, _| {
     if cpu.registers.get_mr() {
         cpu.registers.write_reg(Register::PC, cpu.registers.read_reg(reg.into()));
     }
 })
-instruction!(JMRA, Addres,  None, |cpu, addr, _| {
+instruction!(JMRA, Addres,  None, 0, |cpu, addr, _| {
     if cpu.registers.get_mr() {
         cpu.registers.write_reg(Register::PC, addr);
     }
 })
-instruction!(JMRRA, RegAddr,  None, |cpu, reg_addr, _| {
+instruction!(JMRRA, RegAddr,  None, 0, |cpu, reg_addr, _| {
     if cpu.registers.get_mr() {
         cpu.registers.write_reg(Register::PC, reg_addr);
     }
 })
 // jls
-instruction!(JLSV, Value, None, |cpu, val, _| {
+instruction!(JLSV, Value, None, 0, |cpu, val, _| {
     if cpu.registers.get_ls() {
         cpu.registers.write_reg(Register::PC, val);
     }
 })
-instruction!(JLSR, Register,  None, |cpu, reg, _| {
+instruction!(JLSR, Register,  None, 0, |cpu, reg, _| {
     if cpu.registers.get_ls() {
         cpu.registers.write_reg(Register::PC, cpu.registers.read_reg(reg.into()));
     }
 })
-instruction!(JLSA, Addres,  None, |cpu, addr, _| {
+instruction!(JLSA, Addres,  None, 0, |cpu, addr, _| {
     if cpu.registers.get_ls() {
         cpu.registers.write_reg(Register::PC, addr);
     }
 })
-instruction!(JLSRA, RegAddr,  None, |cpu, reg_addr, _| {
+instruction!(JLSRA, RegAddr,  None, 0, |cpu, reg_addr, _| {
     if cpu.registers.get_ls() {
         cpu.registers.write_reg(Register::PC, reg_addr);
     }
 })
-// interrupts !!! todo!!!
-instruction!(INTV, Value,  None, |_cpu, _val, _| {
-    todo!()
+// interrupts
+instruction!(INTV, Value,  None, 5, |cpu, val, _| {
+    cpu.interrupt(val as u8);
 })
-instruction!(INTR, Register,  None, |_cpu, _reg, _| {
-    todo!()
+instruction!(INTR, Register,  None, 5, |cpu, reg, _| {
+    cpu.interrupt(cpu.registers.read_reg(reg.into()) as u8);
 })
-instruction!(INTA, Addres,  None, |_cpu, _addr, _| {
-    todo!()
+instruction!(INTA, Addres,  None, 6, |cpu, addr, _| {
+    cpu.interrupt(cpu.data_bus.read_byte(addr));
 })
-instruction!(INTRA, RegAddr,  None, |_cpu, _reg_addr, _| {
-    todo!()
+instruction!(INTRA, RegAddr,  None, 6, |cpu, reg_addr, _| {
+    cpu.interrupt(cpu.data_bus.read_byte(reg_addr));
 })
 // call
-instruction!(CALLV, Value, None, |cpu, val, _| {
+instruction!(CALLV, Value, None, 2, |cpu, val, _| {
     // push pc
-    let stp = (cpu.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
-    cpu.registers.write_reg(Register::STP, stp);
-    cpu.data_bus.write_byte(
-        stp,
-        cpu.registers.read_reg(Register::PC) as u8,
-    );
+    cpu.push_addres(cpu.get_pc());
     // jmp reg
     cpu.registers.write_reg(Register::PC, val);
 })
-instruction!(CALLR, Register,  None, |cpu, reg, _| {
+instruction!(CALLR, Register,  None, 2, |cpu, reg, _| {
     // push pc
-    let stp = (cpu.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
-    cpu.registers.write_reg(Register::STP, stp);
-    cpu.data_bus.write_byte(
-        stp,
-        cpu.registers.read_reg(Register::PC) as u8,
-    );
+    cpu.push_addres(cpu.get_pc());
     // jmp reg
     cpu.registers.write_reg(Register::PC, cpu.registers.read_reg(reg.into()));
 })
-instruction!(CALLA, Addres,  None, |cpu, addr, _| {
+instruction!(CALLA, Addres,  None, 2, |cpu, addr, _| {
     // push pc
-    let stp = (cpu.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
-    cpu.registers.write_reg(Register::STP, stp);
-    cpu.data_bus.write_byte(
-        stp,
-        cpu.registers.read_reg(Register::PC) as u8,
-    );
+    cpu.push_addres(cpu.get_pc());
     // jmp addr
     cpu.registers.write_reg(Register::PC, addr);
 })
-instruction!(CALLRA, RegAddr,  None, |cpu, reg_addr, _| {
+instruction!(CALLRA, RegAddr,  None, 2, |cpu, reg_addr, _| {
     // push pc
-    let stp = (cpu.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
-    cpu.registers.write_reg(Register::STP, stp);
-    cpu.data_bus.write_byte(
-        stp,
-        cpu.registers.read_reg(Register::PC) as u8,
-    );
+    cpu.push_addres(cpu.get_pc());
     // jmp reg_addr
     cpu.registers.write_reg(Register::PC, reg_addr);
 })
 // ret
-instruction!(RET, None, None, |cpu, _, _| {
+instruction!(RET, None, None, 2, |cpu, _, _| {
     // pop pc
-    let stp = cpu.registers.read_reg(Register::STP);
-    println!("returning to {}",cpu.data_bus.read_byte(stp) as usize);
-    cpu.registers
-        .write_reg(Register::PC, cpu.data_bus.read_byte(stp) as usize);
-    cpu.registers.write_reg(Register::STP, stp.wrapping_add(1));
+    let pc = cpu.pop_addres();
+    cpu.registers.write_reg(Register::PC, pc);
+})
+// iret
+instruction!(IRET, None, None, 3, |cpu, _, _| {
+    cpu.return_from_interrupt();
+})
+// sti
+instruction!(STI, None, None, 0, |cpu, _, _| {
+    cpu.registers.set_interrupt_enable(true);
+})
+// cli
+instruction!(CLI, None, None, 0, |cpu, _, _| {
+    cpu.registers.set_interrupt_enable(false);
+})
+// brk
+instruction!(BRK, None, None, 0, |cpu, _, _| {
+    cpu.request_break();
+})
+// hlt
+instruction!(HLT, None, None, 0, |cpu, _, _| {
+    cpu.halt();
 })
 );
 
 #[macro_export]
 macro_rules! instruction_set {
-    ($( instruction!($name:ident, $arg1:ident, $arg2:ident, $handler:expr) )* ) => {
-        pub const OPCODES: [(Instruction, &str); 72] = [$(($name, stringify!($name)), )*];
+    ($( instruction!($name:ident, $arg1:ident, $arg2:ident, $accesses:expr, $handler:expr) )* ) => {
+        pub const OPCODES: [(Instruction, &str); 77] = [$(($name, stringify!($name)), )*];
         $(
             pub const $name: Instruction = Instruction {
                 arguments: (ArgumentType::$arg1, ArgumentType::$arg2),
+                memory_accesses: $accesses,
                 handler: $handler,
             };
         )*
@@ -0,0 +1,83 @@
+/// number of maskable irq lines, line 0 has the highest priority
+pub const IRQ_LINES: usize = 8;
+/// vector used for irq line `n` is `IRQ_VECTOR_BASE + n`
+pub const IRQ_VECTOR_BASE: u8 = 0x20;
+/// vector used for the non-maskable interrupt
+pub const NMI_VECTOR: u8 = 0x02;
+/// reading gives the pending lines, writing a 1 bit acknowledges that line
+pub const IRQ_PENDING_ADDRES: usize = 0xFDFE;
+/// a set bit masks that line
+pub const IRQ_MASK_ADDRES: usize = 0xFDFF;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InterruptController {
+    pending: u8,
+    mask: u8,
+    nmi: bool,
+}
+impl InterruptController {
+    pub fn new() -> Self {
+        Self {
+            pending: 0,
+            mask: 0,
+            nmi: false,
+        }
+    }
+    /// request an interrupt on `line`, it stays pending until it is serviced or acknowledged
+    pub fn raise(&mut self, line: usize) {
+        if line < IRQ_LINES {
+            self.pending |= 1 << line;
+        }
+    }
+    pub fn clear(&mut self, line: usize) {
+        if line < IRQ_LINES {
+            self.pending &= !(1 << line);
+        }
+    }
+    pub fn raise_nmi(&mut self) {
+        self.nmi = true;
+    }
+    pub fn get_nmi(&self) -> bool {
+        self.nmi
+    }
+    /// replace all pending lines and the nmi, used to restore save states
+    pub fn set_pending(&mut self, pending: u8, nmi: bool) {
+        self.pending = pending;
+        self.nmi = nmi;
+    }
+    pub fn get_pending(&self) -> u8 {
+        self.pending
+    }
+    pub fn acknowledge(&mut self, lines: u8) {
+        self.pending &= !lines;
+    }
+    pub fn get_mask(&self) -> u8 {
+        self.mask
+    }
+    pub fn set_mask(&mut self, mask: u8) {
+        self.mask = mask;
+    }
+    /// whether `take` would return an interrupt
+    pub fn is_active(&self, enabled: bool) -> bool {
+        self.nmi || (enabled && self.pending & !self.mask != 0)
+    }
+    /// takes the interrupt that should be serviced next and returns its vector
+    ///
+    /// the nmi is always taken, the irq lines only when `enabled` is set.
+    pub fn take(&mut self, enabled: bool) -> Option<u8> {
+        if self.nmi {
+            self.nmi = false;
+            return Some(NMI_VECTOR);
+        }
+        if !enabled {
+            return None;
+        }
+        let active = self.pending & !self.mask;
+        if active == 0 {
+            return None;
+        }
+        let line = active.trailing_zeros() as usize;
+        self.clear(line);
+        Some(IRQ_VECTOR_BASE + line as u8)
+    }
+}
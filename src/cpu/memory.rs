@@ -1,81 +1,413 @@
-use std::fmt::Debug;
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{Debug, Display},
+    io,
+};
+
+use crate::{load_bin_file, MAX_BYTES};
+
+use super::interrupts::{InterruptController, IRQ_MASK_ADDRES, IRQ_PENDING_ADDRES};
+use super::save_state::{SaveStateError, StateReader, StateWriter};
+
+/// what the bus does with an access to an address no driver is mapped at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpenBus {
+    /// reads return the value, writes are ignored
+    Value(u8),
+    /// the access ends the instruction in `Fault::BusError`
+    Fault,
+    /// reads return 0xFF and the address is added to the open bus log
+    Log,
+}
+
+#[derive(Debug)]
+pub struct Mapping {
+    pub base: usize,
+    /// number of bytes the mapping covers, the driver is mirrored if it is smaller
+    pub length: usize,
+    pub driver: Box<dyn DataDriver>,
+}
+impl Mapping {
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.base && addr < self.base + self.length
+    }
+    /// the address relative to the start of the driver
+    pub fn get_offset(&self, addr: usize) -> usize {
+        (addr - self.base) % (self.driver.get_size() / 8).max(1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapError {
+    /// the new mapping overlaps the mapping at `base`
+    Overlap {
+        base: usize,
+        length: usize,
+    },
+    Empty,
+    /// the new mapping reaches past the end of memory
+    OutOfRange {
+        base: usize,
+        length: usize,
+    },
+}
+impl Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapError::Overlap { base, length } => {
+                write!(f, "mapping overlaps {:#06X}..{:#06X}", base, base + length)
+            }
+            MapError::Empty => write!(f, "mapping has no bytes"),
+            MapError::OutOfRange { base, length } => write!(
+                f,
+                "mapping {:#06X}+{:#06X} reaches past {:#06X}",
+                base, length, MAX_BYTES
+            ),
+        }
+    }
+}
+
+/// a byte the cpu wrote, see `DataBus::start_write_log`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryWrite {
+    pub addr: usize,
+    /// the byte at `addr` before the write as seen by `peek_byte`
+    pub old: u8,
+    pub new: u8,
+}
+
+/// the data accesses of one `CPU::clock`, see `CPU::record_accesses`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Accesses {
+    /// addresses that were read, without fetching the instruction itself
+    pub reads: Vec<usize>,
+    pub writes: Vec<MemoryWrite>,
+}
 
 #[derive(Debug)]
 pub struct DataBus {
-    pub drivers: Vec<Box<dyn DataDriver>>,
+    pub mappings: Vec<Mapping>,
+    pub interrupt_controller: InterruptController,
+    pub open_bus: OpenBus,
+    cycles: usize,
+    /// address of the last access that hit no driver
+    error: Cell<Option<usize>>,
+    open_bus_log: RefCell<Vec<usize>>,
+    write_log: Option<Vec<MemoryWrite>>,
+    read_log: RefCell<Option<Vec<usize>>>,
 }
 impl DataBus {
     pub fn new() -> Self {
-        DataBus { drivers: vec![] }
+        DataBus {
+            mappings: vec![],
+            interrupt_controller: InterruptController::new(),
+            open_bus: OpenBus::Fault,
+            cycles: 0,
+            error: Cell::new(None),
+            open_bus_log: RefCell::new(vec![]),
+            write_log: None,
+            read_log: RefCell::new(None),
+        }
     }
     pub fn read_byte(&self, addr: usize) -> u8 {
-        let mut current_max_size = 0;
-        for driver in &self.drivers {
-            current_max_size += driver.get_size();
-            if (addr as usize) * 8 < current_max_size {
-                // println!("reading: addr: {}, data: {}", addr, driver.read_byte(addr));
-                return driver.read_byte(addr);
-            }
+        if let Some(read_log) = self.read_log.borrow_mut().as_mut() {
+            read_log.push(addr);
+        }
+        match addr {
+            IRQ_PENDING_ADDRES => return self.interrupt_controller.get_pending(),
+            IRQ_MASK_ADDRES => return self.interrupt_controller.get_mask(),
+            _ => {}
+        }
+        if let Some(mapping) = self.mappings.iter().find(|x| x.contains(addr)) {
+            return mapping.driver.read_byte(mapping.get_offset(addr));
         }
-        println!("out of bounds!!! addr {}", addr);
-        return 0;
+        self.open_bus_access(addr)
     }
     pub fn write_byte(&mut self, addr: usize, data: u8) {
-        let mut current_max_size = 0;
-        for driver in &mut self.drivers {
-            current_max_size += driver.get_size();
-            // println!("addr: {addr}");
-            if (addr as usize) * 8 < current_max_size {
-                driver.write_byte(addr, data);
+        if matches!(addr, IRQ_PENDING_ADDRES | IRQ_MASK_ADDRES) {
+            let old = self.peek_byte(addr);
+            if let Some(write_log) = &mut self.write_log {
+                write_log.push(MemoryWrite {
+                    addr,
+                    old,
+                    new: data,
+                });
+            }
+        }
+        match addr {
+            IRQ_PENDING_ADDRES => return self.interrupt_controller.acknowledge(data),
+            IRQ_MASK_ADDRES => return self.interrupt_controller.set_mask(data),
+            _ => {}
+        }
+        if let Some(mapping) = self.mappings.iter_mut().find(|x| x.contains(addr)) {
+            if mapping.driver.faults_on_write() {
+                self.error.set(Some(addr));
                 return;
             }
+            let offset = mapping.get_offset(addr);
+            if let Some(write_log) = &mut self.write_log {
+                let old = mapping.driver.peek_byte(offset);
+                write_log.push(MemoryWrite {
+                    addr,
+                    old,
+                    new: data,
+                });
+            }
+            mapping.driver.write_byte(offset, data);
+            return;
+        }
+        self.open_bus_access(addr);
+    }
+    /// undo a write recorded in the write log, it is not logged itself
+    pub fn restore_byte(&mut self, addr: usize, data: u8) {
+        self.poke_byte(addr, data)
+    }
+    /// like `write_byte` but for debuggers, it is not logged, never reports a bus error
+    /// and devices take it like a restored byte
+    pub fn poke_byte(&mut self, addr: usize, data: u8) {
+        match addr {
+            IRQ_PENDING_ADDRES => {
+                let nmi = self.interrupt_controller.get_nmi();
+                return self.interrupt_controller.set_pending(data, nmi);
+            }
+            IRQ_MASK_ADDRES => return self.interrupt_controller.set_mask(data),
+            _ => {}
+        }
+        if let Some(mapping) = self.mappings.iter_mut().find(|x| x.contains(addr)) {
+            let offset = mapping.get_offset(addr);
+            mapping.driver.restore_byte(offset, data);
+        }
+    }
+    /// like `read_byte` but without side effects on devices or the open bus log
+    pub fn peek_byte(&self, addr: usize) -> u8 {
+        match addr {
+            IRQ_PENDING_ADDRES => return self.interrupt_controller.get_pending(),
+            IRQ_MASK_ADDRES => return self.interrupt_controller.get_mask(),
+            _ => {}
+        }
+        if let Some(mapping) = self.mappings.iter().find(|x| x.contains(addr)) {
+            return mapping.driver.peek_byte(mapping.get_offset(addr));
+        }
+        match self.open_bus {
+            OpenBus::Value(value) => value,
+            OpenBus::Fault => 0,
+            OpenBus::Log => 0xFF,
         }
-        println!("out of bounds!!! addr {}", addr);
-        panic!();
     }
+    fn open_bus_access(&self, addr: usize) -> u8 {
+        match self.open_bus {
+            OpenBus::Value(value) => value,
+            OpenBus::Fault => {
+                self.error.set(Some(addr));
+                0
+            }
+            OpenBus::Log => {
+                self.open_bus_log.borrow_mut().push(addr);
+                0xFF
+            }
+        }
+    }
+    /// map `driver` after the last mapped byte
     pub fn add_driver(&mut self, driver: Box<dyn DataDriver>) {
-        self.drivers.push(driver);
+        let base = self
+            .mappings
+            .iter()
+            .map(|x| x.base + x.length)
+            .max()
+            .unwrap_or(0);
+        let length = driver.get_size() / 8;
+        self.mappings.push(Mapping {
+            base,
+            length,
+            driver,
+        });
     }
-}
-#[derive(Debug)]
-pub struct BitMap {
-    data: Vec<bool>,
-    size: usize,
-}
-impl BitMap {
-    pub fn new(size: usize) -> Self {
-        Self {
-            data: vec![false; size],
-            size,
+    /// map `driver` at `base`, it sees addresses relative to `base`
+    pub fn map(&mut self, base: usize, driver: Box<dyn DataDriver>) -> Result<(), MapError> {
+        let length = driver.get_size() / 8;
+        self.map_mirrored(base, length, driver)
+    }
+    /// map `driver` at `base` and repeat it until `length` bytes are covered
+    ///
+    /// the mapping has to fit in memory and leave the interrupt controller
+    /// registers free, they are always in front of any mapping.
+    pub fn map_mirrored(
+        &mut self,
+        base: usize,
+        length: usize,
+        driver: Box<dyn DataDriver>,
+    ) -> Result<(), MapError> {
+        if length == 0 || driver.get_size() < 8 {
+            return Err(MapError::Empty);
+        }
+        if base.checked_add(length).is_none_or(|end| end > MAX_BYTES) {
+            return Err(MapError::OutOfRange { base, length });
+        }
+        if base <= IRQ_MASK_ADDRES && IRQ_PENDING_ADDRES < base + length {
+            return Err(MapError::Overlap {
+                base: IRQ_PENDING_ADDRES,
+                length: 2,
+            });
         }
+        for mapping in &self.mappings {
+            if base < mapping.base + mapping.length && mapping.base < base + length {
+                return Err(MapError::Overlap {
+                    base: mapping.base,
+                    length: mapping.length,
+                });
+            }
+        }
+        self.mappings.push(Mapping {
+            base,
+            length,
+            driver,
+        });
+        Ok(())
     }
-    pub fn set_bit(&mut self, addr: usize, data: bool) {
-        self.data[addr] = data;
+    /// map a read only copy of `data` at `base`
+    pub fn map_rom(&mut self, base: usize, data: Vec<u8>) -> Result<(), MapError> {
+        self.map(base, Box::new(ROM::new(data)))
     }
-    pub fn get_bit(&self, addr: usize) -> bool {
-        return self.data[addr];
+    /// map the binary image at `file_path` read only at `base`
+    pub fn map_rom_file(&mut self, base: usize, file_path: String) -> io::Result<()> {
+        let rom = ROM::from_file(file_path)?;
+        self.map(base, Box::new(rom))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
     }
-    pub fn get_size(&self) -> usize {
-        return self.size;
+    /// the address of the last out of bounds access since the previous call
+    pub fn take_error(&self) -> Option<usize> {
+        self.error.take()
     }
-    pub fn write_byte(&mut self, addr: usize, data: u8) {
-        for i in 0..8 {
-            self.set_bit((addr * 8) + i, data.get_bit(7 - i));
+    /// record every write to a mapped address until `take_write_log`
+    pub fn start_write_log(&mut self) {
+        self.write_log = Some(vec![]);
+    }
+    /// the writes since `start_write_log` and stop recording
+    pub fn take_write_log(&mut self) -> Vec<MemoryWrite> {
+        self.write_log.take().unwrap_or_default()
+    }
+    /// record every address read until `take_read_log`
+    pub fn start_read_log(&self) {
+        self.read_log.replace(Some(vec![]));
+    }
+    /// the reads since `start_read_log` and stop recording
+    pub fn take_read_log(&self) -> Vec<usize> {
+        self.read_log.take().unwrap_or_default()
+    }
+    /// the unmapped addresses accessed with `OpenBus::Log` since the previous call
+    pub fn take_open_bus_log(&self) -> Vec<usize> {
+        self.open_bus_log.take()
+    }
+    /// cycles that passed on the bus, the drivers see them through `update`
+    pub fn get_cycles(&self) -> usize {
+        self.cycles
+    }
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.cycles as u64);
+        writer.write_u8(self.interrupt_controller.get_pending());
+        writer.write_u8(self.interrupt_controller.get_mask());
+        writer.write_u8(self.interrupt_controller.get_nmi() as u8);
+        writer.write_u16(self.mappings.len() as u16);
+        for mapping in &self.mappings {
+            writer.write_u32(mapping.base as u32);
+            writer.write_u32(mapping.length as u32);
+            writer.write_bytes(&mapping.driver.save_state());
         }
     }
-    pub fn read_byte(&self, addr: usize) -> u8 {
-        let mut res: u8 = 0;
-        for i in 0..8 {
-            res.set_bit(7 - i, self.get_bit((addr * 8) + i));
+    /// restore a state made by `save_state`, nothing changes when it can not be loaded
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let cycles = reader.read_u64()? as usize;
+        let pending = reader.read_u8()?;
+        let mask = reader.read_u8()?;
+        let nmi = reader.read_u8()? != 0;
+        let count = reader.read_u16()? as usize;
+        let mut states = vec![];
+        for index in 0..count.max(self.mappings.len()) {
+            let mapping = match self.mappings.get(index) {
+                Some(mapping) if index < count => mapping,
+                _ => return Err(SaveStateError::MemoryMap { index }),
+            };
+            let base = reader.read_u32()? as usize;
+            let length = reader.read_u32()? as usize;
+            if base != mapping.base || length != mapping.length {
+                return Err(SaveStateError::MemoryMap { index });
+            }
+            states.push(reader.read_bytes()?);
+        }
+        // a driver can still refuse its state, the ones loaded before it get theirs back
+        let backups: Vec<Vec<u8>> = self
+            .mappings
+            .iter()
+            .map(|x| x.driver.save_state())
+            .collect();
+        for (index, state) in states.into_iter().enumerate() {
+            if let Err(msg) = self.mappings[index].driver.load_state(state) {
+                for (mapping, backup) in self.mappings.iter_mut().zip(&backups).take(index) {
+                    let _ = mapping.driver.load_state(backup);
+                }
+                return Err(SaveStateError::Driver { index, msg });
+            }
+        }
+        self.cycles = cycles;
+        self.interrupt_controller.set_mask(mask);
+        self.interrupt_controller.set_pending(pending, nmi);
+        Ok(())
+    }
+    /// whether a driver can still raise an irq line by itself
+    pub fn can_interrupt(&self) -> bool {
+        self.mappings.iter().any(|x| x.driver.can_interrupt())
+    }
+    /// let `cycles` cycles pass for every driver, called by the cpu after each instruction
+    pub fn update(&mut self, cycles: usize) {
+        self.cycles += cycles;
+        for mapping in &mut self.mappings {
+            mapping
+                .driver
+                .update(cycles, &mut self.interrupt_controller);
         }
-        return res;
     }
 }
-
 pub trait DataDriver {
     fn get_size(&self) -> usize;
     fn write_byte(&mut self, addr: usize, data: u8);
     fn read_byte(&self, addr: usize) -> u8;
+    /// read without side effects, for debug views of devices whose reads change them
+    fn peek_byte(&self, addr: usize) -> u8 {
+        self.read_byte(addr)
+    }
+    /// put back a byte the cpu overwrote when stepping back, see `CPU::step_back`
+    ///
+    /// devices whose writes can not be taken back ignore it.
+    fn restore_byte(&mut self, addr: usize, data: u8) {
+        self.write_byte(addr, data)
+    }
+    /// called after every instruction with the cycles it took, devices raise their irq lines here
+    fn update(&mut self, _cycles: usize, _interrupts: &mut InterruptController) {}
+    /// whether `update` may still raise an irq line, a halted cpu only waits for those
+    fn can_interrupt(&self) -> bool {
+        false
+    }
+    /// when set the bus does not call `write_byte` but reports a bus error
+    fn faults_on_write(&self) -> bool {
+        false
+    }
+    /// everything needed to restore the driver, by default all of its bytes
+    fn save_state(&self) -> Vec<u8> {
+        (0..self.get_size() / 8)
+            .map(|i| self.peek_byte(i))
+            .collect()
+    }
+    /// by default every byte is put back with `restore_byte`, so devices do
+    /// not see it as a write
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != self.get_size() / 8 {
+            return Err(format!("expected {} bytes", self.get_size() / 8));
+        }
+        for (i, byte) in state.iter().enumerate() {
+            self.restore_byte(i, *byte);
+        }
+        Ok(())
+    }
 }
 impl Debug for dyn DataDriver {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -83,7 +415,7 @@ impl Debug for dyn DataDriver {
         let mut data_string = "[".to_string();
 
         for i in 0..self.get_size() / 8 {
-            data[i] = self.read_byte(i);
+            data[i] = self.peek_byte(i);
             data_string += format!("{:#04X}, ", data[i]).as_str();
         }
         data_string.pop(); // remover the ", " from the last part
@@ -96,26 +428,82 @@ impl Debug for dyn DataDriver {
 }
 
 pub struct RAM {
-    bit_map: BitMap,
+    data: Vec<u8>,
 }
 impl RAM {
+    /// `size` is in bits like `DataDriver::get_size`
     pub fn new(size: usize) -> Self {
         Self {
-            bit_map: BitMap::new(size),
+            data: vec![0; size / 8],
         }
     }
 }
 impl DataDriver for RAM {
     fn get_size(&self) -> usize {
-        self.bit_map.get_size()
+        self.data.len() * 8
     }
 
     fn write_byte(&mut self, addr: usize, data: u8) {
-        self.bit_map.write_byte(addr, data)
+        self.data[addr] = data
     }
 
     fn read_byte(&self, addr: usize) -> u8 {
-        self.bit_map.read_byte(addr)
+        self.data[addr]
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != self.data.len() {
+            return Err(format!("expected {} bytes", self.data.len()));
+        }
+        self.data.copy_from_slice(state);
+        Ok(())
+    }
+}
+/// read only memory, writes are ignored unless `fault_on_write` is set
+pub struct ROM {
+    data: Vec<u8>,
+    pub fault_on_write: bool,
+}
+impl ROM {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            fault_on_write: false,
+        }
+    }
+    pub fn from_file(file_path: String) -> io::Result<Self> {
+        Ok(Self::new(load_bin_file(file_path)?))
+    }
+}
+impl DataDriver for ROM {
+    fn get_size(&self) -> usize {
+        self.data.len() * 8
+    }
+
+    fn write_byte(&mut self, _addr: usize, _data: u8) {}
+
+    fn read_byte(&self, addr: usize) -> u8 {
+        self.data[addr]
+    }
+
+    fn faults_on_write(&self) -> bool {
+        self.fault_on_write
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != self.data.len() {
+            return Err(format!("expected {} bytes", self.data.len()));
+        }
+        self.data.copy_from_slice(state);
+        Ok(())
     }
 }
 pub trait ManipulateBits {
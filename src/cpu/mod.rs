@@ -1,25 +1,71 @@
+pub mod debugger;
+pub mod execution;
+pub mod gdb;
+pub mod history;
 #[macro_use]
 pub mod instructions;
+pub mod interrupts;
 pub mod memory;
+pub mod save_state;
+pub mod throttle;
+pub mod trace;
+pub mod tui;
 
-use std::fmt::Debug;
+use std::{fmt::Debug, io::Write};
 
 use crate::{cpu::instructions::ArgumentType, utils::SetGetBytes, RAM_BYTES};
 
 use self::{
-    instructions::{Instruction, OPCODES},
-    memory::{BitMap, DataBus},
+    execution::{Executed, Fault},
+    history::{History, UndoEntry},
+    instructions::{Instruction, INTERRUPT_CYCLES, OPCODES},
+    memory::{Accesses, DataBus, ManipulateBits},
+    trace::TraceRecord,
 };
 
-pub const FLAGS_OFFSET: usize = 48;
+/// bit positions of the flags in the flags register
+pub const FLAG_EQ: usize = 7;
+pub const FLAG_MR: usize = 6;
+pub const FLAG_LS: usize = 5;
+pub const FLAG_ZERO: usize = 4;
+pub const FLAG_CARRY: usize = 3;
+pub const FLAG_INTERRUPT_ENABLE: usize = 2;
+/// start of the interrupt vector table, 256 vectors of 2 bytes (high byte first)
+pub const INTERRUPT_VECTOR_TABLE: usize = 0xFE00;
 pub const REGISTER_NAMES: [&str; 10] = [
     "ax", "bx", "cx", "dx", "pc", "pcl", "pch", "flags", "stk", "stp",
 ];
+/// the registers that make up the cpu state with their size in bytes, pcl and pch are part of pc
+pub const STATE_REGISTERS: [(Register, usize); 8] = [
+    (Register::AX, 1),
+    (Register::BX, 1),
+    (Register::CX, 1),
+    (Register::DX, 1),
+    (Register::PC, 2),
+    (Register::FLAGS, 1),
+    (Register::STK, 2),
+    (Register::STP, 2),
+];
 
 pub struct CPU {
     pub data_bus: DataBus,
     pub registers: Registers,
     pub instructions: Vec<(Instruction, &'static str)>,
+    /// every clock is written here as a `TraceRecord` line when set, faults
+    /// included, a halted cpu only once
+    pub trace: Option<Box<dyn Write>>,
+    /// undo log for `step_back` when set
+    pub history: Option<History>,
+    /// keep the memory accesses of every clock for `get_accesses`
+    pub record_accesses: bool,
+    accesses: Accesses,
+    break_requested: bool,
+    halted: bool,
+    last_interrupt: Option<u8>,
+    /// the decoded arguments of the last instruction, for the trace of a fault
+    last_arguments: (usize, usize),
+    /// the halt was traced already, a waiting cpu is only traced once
+    halt_traced: bool,
 }
 
 impl Debug for CPU {
@@ -28,6 +74,9 @@ impl Debug for CPU {
             .field("data_bus", &self.data_bus)
             .field("registers", &self.registers)
             .field("instructions", &self.instructions)
+            .field("trace", &self.trace.is_some())
+            .field("history", &self.history.as_ref().map(|x| x.len()))
+            .field("halted", &self.halted)
             .finish()
     }
 }
@@ -38,16 +87,197 @@ impl CPU {
             data_bus: DataBus::new(),
             registers: Registers::new(),
             instructions: OPCODES.to_vec(),
+            trace: None,
+            history: None,
+            record_accesses: false,
+            accesses: Accesses::default(),
+            break_requested: false,
+            halted: false,
+            last_interrupt: None,
+            last_arguments: (0, 0),
+            halt_traced: false,
         }
     }
     pub fn mount_data_bus(&mut self, data_bus: DataBus) {
         self.data_bus = data_bus;
     }
-    pub fn clock(&mut self) {
+    /// execute a single instruction, entering a pending interrupt first
+    ///
+    /// a halted cpu returns `Fault::Halt` until an interrupt wakes it up,
+    /// every one of those calls lets the devices see one cycle pass.
+    pub fn clock(&mut self) -> Result<Executed, Fault> {
+        if self.trace.is_none() && self.history.is_none() && !self.record_accesses {
+            return self.execute();
+        }
+        let registers = self.registers.clone();
+        let halted = self.halted;
+        let cycles = self.get_cycles();
+        self.data_bus.start_write_log();
+        if self.record_accesses {
+            self.data_bus.start_read_log();
+        }
+        let result = self.execute();
+        let writes = self.data_bus.take_write_log();
+        if let Some(trace) = &mut self.trace {
+            let record = match &result {
+                Ok(executed) => Some(TraceRecord::new(
+                    executed,
+                    &registers,
+                    &self.registers,
+                    writes.clone(),
+                )),
+                Err(Fault::Halt { .. }) if self.halt_traced => None,
+                Err(fault) => {
+                    let pc = fault.get_pc();
+                    let opcode = self.data_bus.peek_byte(pc);
+                    let executed = Executed {
+                        pc,
+                        opcode,
+                        name: match self.instructions.get(opcode as usize) {
+                            Some(instruction) => instruction.1,
+                            None => "???",
+                        },
+                        arguments: self.last_arguments,
+                        cycles: self.data_bus.get_cycles() - cycles,
+                        interrupt: self.last_interrupt,
+                    };
+                    let mut record =
+                        TraceRecord::new(&executed, &registers, &self.registers, writes.clone());
+                    record.fault = Some(fault.clone());
+                    Some(record)
+                }
+            };
+            if let Some(record) = record {
+                let _ = writeln!(trace, "{}", record);
+            }
+            self.halt_traced = matches!(result, Err(Fault::Halt { .. }));
+        }
+        if self.record_accesses {
+            let mut reads = self.data_bus.take_read_log();
+            if let Ok(executed) = &result {
+                let size = self.instructions[executed.opcode as usize].0.get_size();
+                let fetched = executed.pc..executed.pc + size;
+                reads.retain(|addr| !fetched.contains(addr));
+            }
+            self.accesses = Accesses {
+                reads,
+                writes: writes.clone(),
+            };
+        }
+        if let Some(history) = &mut self.history {
+            if registers != self.registers || halted != self.halted || !writes.is_empty() {
+                history.push(UndoEntry {
+                    registers,
+                    halted,
+                    writes,
+                });
+            }
+        }
+        result
+    }
+    /// the memory accesses of the last clock, empty unless `record_accesses` is set
+    pub fn get_accesses(&self) -> &Accesses {
+        &self.accesses
+    }
+    fn execute(&mut self) -> Result<Executed, Fault> {
+        self.data_bus.take_error(); // ignore accesses made from outside of the cpu
+        let enabled = self.registers.get_interrupt_enable();
+        let interrupt = self.data_bus.interrupt_controller.take(enabled);
+        self.last_interrupt = interrupt;
+        if let Some(vector) = interrupt {
+            self.halted = false;
+            self.interrupt(vector);
+        } else if self.halted {
+            self.last_arguments = (0, 0);
+            self.data_bus.update(1);
+            let pc = (self.get_pc() as u16).wrapping_sub(1) as usize;
+            return Err(Fault::Halt { pc });
+        }
         let pc = self.inc_pc();
         let opcode = self.data_bus.read_byte(pc);
-        // println!("opcode: {} pc: {}", opcode, pc);
-        self.handle_instruction(opcode);
+        let mut executed = match self.handle_instruction(opcode) {
+            Ok(executed) => executed,
+            Err(fault) => {
+                // the interrupt was entered even though its handler faulted
+                if interrupt.is_some() {
+                    self.data_bus.update(INTERRUPT_CYCLES);
+                }
+                return Err(fault);
+            }
+        };
+        if interrupt.is_some() {
+            executed.interrupt = interrupt;
+            executed.cycles += INTERRUPT_CYCLES;
+        }
+        self.data_bus.update(executed.cycles);
+        Ok(executed)
+    }
+    /// vector of the interrupt the last `clock` entered, also when its instruction faulted
+    pub fn get_last_interrupt(&self) -> Option<u8> {
+        self.last_interrupt
+    }
+    /// cycles that passed since the cpu was created, including halted ones
+    pub fn get_cycles(&self) -> usize {
+        self.data_bus.get_cycles()
+    }
+    /// run until the program halts, returns the number of cycles that were run
+    ///
+    /// a halt keeps waiting while `is_waiting`, otherwise it stops.
+    pub fn run(&mut self) -> Result<usize, Fault> {
+        self.run_until(|_| false)
+    }
+    /// run until the program halts or at least `max_cycles` cycles have passed
+    pub fn run_for(&mut self, max_cycles: usize) -> Result<usize, Fault> {
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            match self.run_step()? {
+                Some(step) => cycles += step,
+                None => break,
+            }
+        }
+        Ok(cycles)
+    }
+    /// run until the program halts or `predicate` holds after an instruction
+    pub fn run_until<F: FnMut(&CPU) -> bool>(&mut self, mut predicate: F) -> Result<usize, Fault> {
+        let mut cycles = 0;
+        while let Some(step) = self.run_step()? {
+            cycles += step;
+            if predicate(self) {
+                break;
+            }
+        }
+        Ok(cycles)
+    }
+    /// the cycles one `clock` took, `None` once the cpu halted for good
+    fn run_step(&mut self) -> Result<Option<usize>, Fault> {
+        match self.clock() {
+            Ok(executed) => Ok(Some(executed.cycles)),
+            Err(Fault::Halt { .. }) if self.is_waiting() => Ok(Some(1)),
+            Err(Fault::Halt { .. }) => Ok(None),
+            Err(fault) => Err(fault),
+        }
+    }
+    /// stop executing after the current instruction, see `clock`
+    pub fn halt(&mut self) {
+        self.halted = true;
+    }
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+    /// whether an interrupt can still wake the halted cpu, it is pending or
+    /// interrupts are enabled and a device can raise one
+    pub fn is_waiting(&self) -> bool {
+        let enabled = self.registers.get_interrupt_enable();
+        self.data_bus.interrupt_controller.is_active(enabled)
+            || (enabled && self.data_bus.can_interrupt())
+    }
+    /// continue after a halt without waiting for an interrupt
+    pub fn resume(&mut self) {
+        self.halted = false;
+    }
+    /// make the current instruction end in `Fault::Breakpoint`
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
     }
     pub fn get_pc(&self) -> usize {
         self.registers.read_reg(Register::PC)
@@ -58,6 +288,68 @@ impl CPU {
         return pc;
     }
 
+    pub fn push_byte(&mut self, data: u8) {
+        let stp = (self.registers.read_reg(Register::STP) as u16).wrapping_sub(1) as usize;
+        self.registers.write_reg(Register::STP, stp); // decrement stp
+        self.data_bus.write_byte(stp, data); // write at stp
+    }
+    pub fn pop_byte(&mut self) -> u8 {
+        let stp = self.registers.read_reg(Register::STP);
+        let data = self.data_bus.read_byte(stp);
+        self.registers
+            .write_reg(Register::STP, (stp as u16).wrapping_add(1) as usize);
+        data
+    }
+    /// push a 16-bit address, afterwards `[stp]` is the low byte and `[stp + 1]` the high byte
+    pub fn push_addres(&mut self, addr: usize) {
+        self.push_byte(addr.get_byte(1));
+        self.push_byte(addr.get_byte(0));
+    }
+    pub fn pop_addres(&mut self) -> usize {
+        let mut addr: usize = 0;
+        addr.set_byte(0, self.pop_byte());
+        addr.set_byte(1, self.pop_byte());
+        addr
+    }
+    /// read the 16-bit address pushed `offset` bytes above stp without popping it
+    ///
+    /// `peek_addres(0)` is the return address of the current `call`.
+    pub fn peek_addres(&self, offset: usize) -> usize {
+        let low = (self.registers.read_reg(Register::STP) as u16).wrapping_add(offset as u16);
+        let high = low.wrapping_add(1);
+        let mut addr: usize = 0;
+        addr.set_byte(0, self.data_bus.peek_byte(low as usize));
+        addr.set_byte(1, self.data_bus.peek_byte(high as usize));
+        addr
+    }
+    /// address of the handler for `vector` as stored in the interrupt vector table
+    pub fn get_interrupt_vector(&self, vector: u8) -> usize {
+        let entry = INTERRUPT_VECTOR_TABLE + vector as usize * 2;
+        let mut addr: usize = 0;
+        addr.set_byte(1, self.data_bus.read_byte(entry));
+        addr.set_byte(0, self.data_bus.read_byte(entry + 1));
+        addr
+    }
+    /// enter the handler of `vector`
+    ///
+    /// pushes pc like `call` does and then flags so that after the call
+    /// `[stp]` is flags, `[stp + 1]` is pcl and `[stp + 2]` is pch.
+    /// the handler runs with interrupts disabled, `iret` undoes all of this.
+    pub fn interrupt(&mut self, vector: u8) {
+        self.push_addres(self.get_pc());
+        self.push_byte(self.registers.read_reg(Register::FLAGS) as u8);
+        self.registers.set_interrupt_enable(false);
+        let handler = self.get_interrupt_vector(vector);
+        self.registers.write_reg(Register::PC, handler);
+    }
+    /// return from an interrupt handler entered through `interrupt`
+    pub fn return_from_interrupt(&mut self) {
+        let flags = self.pop_byte();
+        let pc = self.pop_addres();
+        self.registers.write_reg(Register::FLAGS, flags as usize);
+        self.registers.write_reg(Register::PC, pc);
+    }
+
     fn read_value(&mut self, pc: usize) -> usize {
         return self.data_bus.read_byte(pc) as usize;
     }
@@ -82,11 +374,14 @@ impl CPU {
         return addr as usize;
     }
 
-    pub fn handle_instruction(&mut self, opcode: u8) {
-        if opcode as usize > self.instructions.len() {
-            return;
+    /// execute `opcode`, which was fetched from the byte before pc
+    pub fn handle_instruction(&mut self, opcode: u8) -> Result<Executed, Fault> {
+        let pc = (self.get_pc() as u16).wrapping_sub(1) as usize;
+        if opcode as usize >= self.instructions.len() {
+            self.registers.write_reg(Register::PC, pc);
+            self.last_arguments = (0, 0);
+            return Err(Fault::IllegalOpcode { pc, opcode });
         }
-        let start_pc = self.registers.read_reg(Register::PC);
         let instruction = self.instructions[opcode as usize].clone();
         // println!("opcode: {} instruction: {}",opcode, instruction.1);
         let arg1 = match instruction.0.arguments.0 {
@@ -117,99 +412,108 @@ impl CPU {
                 }
             }
         };
-        println!("{}: {} {}, {}", start_pc, instruction.1, arg1, arg2);
-        (instruction.0.handler)(self, arg1, arg2)
+        self.last_arguments = (arg1, arg2);
+        let cycles = instruction.0.get_cycles();
+        (instruction.0.handler)(self, arg1, arg2);
+        if let Some(addr) = self.data_bus.take_error() {
+            return Err(Fault::BusError { pc, addr });
+        }
+        if self.break_requested {
+            self.break_requested = false;
+            return Err(Fault::Breakpoint { pc });
+        }
+        Ok(Executed {
+            pc,
+            opcode,
+            name: instruction.1,
+            arguments: (arg1, arg2),
+            cycles,
+            interrupt: None,
+        })
     }
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Registers {
-    bit_map: BitMap,
+    general: [u8; 4],
+    pc: u16,
+    flags: u8,
+    stk: u16,
+    stp: u16,
 }
 impl Registers {
     pub fn new() -> Self {
-        let mut new = Self {
-            bit_map: BitMap::new(88),
-        };
-        new.write_reg(Register::STK, RAM_BYTES - 1);
-        new.write_reg(Register::STP, RAM_BYTES - 1);
-        return new;
+        Self {
+            general: [0; 4],
+            pc: 0,
+            flags: 0,
+            stk: (RAM_BYTES - 1) as u16,
+            stp: (RAM_BYTES - 1) as u16,
+        }
     }
     pub fn read_reg(&self, register: Register) -> usize {
         match register {
-            Register::AX => self.bit_map.read_byte(0) as usize,
-            Register::BX => self.bit_map.read_byte(1) as usize,
-            Register::CX => self.bit_map.read_byte(2) as usize,
-            Register::DX => self.bit_map.read_byte(3) as usize,
-            Register::PC => {
-                (self.bit_map.read_byte(4) as usize) | ((self.bit_map.read_byte(5) as usize) << 8)
-            }
-            Register::PCL => self.bit_map.read_byte(4) as usize,
-            Register::PCH => self.bit_map.read_byte(5) as usize,
-            Register::FLAGS => self.bit_map.read_byte(6) as usize,
-            Register::STK => {
-                (self.bit_map.read_byte(7) as usize) | ((self.bit_map.read_byte(8) as usize) << 8)
-            }
-            Register::STP => {
-                (self.bit_map.read_byte(9) as usize) | ((self.bit_map.read_byte(10) as usize) << 8)
-            }
+            Register::AX => self.general[0] as usize,
+            Register::BX => self.general[1] as usize,
+            Register::CX => self.general[2] as usize,
+            Register::DX => self.general[3] as usize,
+            Register::PC => self.pc as usize,
+            Register::PCL => self.pc.get_byte(0) as usize,
+            Register::PCH => self.pc.get_byte(1) as usize,
+            Register::FLAGS => self.flags as usize,
+            Register::STK => self.stk as usize,
+            Register::STP => self.stp as usize,
         }
     }
     pub fn write_reg(&mut self, register: Register, data: usize) {
-        // println!("register: {:?}, data: {}",register, data);
-        // if register == Register::STP {
-        //     println!("register: {:?}, data: {}",register, data);
-        // }
         match register {
-            Register::AX => self.bit_map.write_byte(0, data as u8),
-            Register::BX => self.bit_map.write_byte(1, data as u8),
-            Register::CX => self.bit_map.write_byte(2, data as u8),
-            Register::DX => self.bit_map.write_byte(3, data as u8),
-            Register::PC => {
-                self.bit_map.write_byte(4, data.get_byte(0));
-                self.bit_map.write_byte(5, data.get_byte(1))
-            }
-            Register::PCL => self.bit_map.write_byte(4, data as u8),
-            Register::PCH => self.bit_map.write_byte(5, data as u8),
-            Register::FLAGS => self.bit_map.write_byte(6, data as u8),
-            Register::STK => {
-                self.bit_map.write_byte(7, data.get_byte(0));
-                self.bit_map.write_byte(8, data.get_byte(1))
-            }
-            Register::STP => {
-                self.bit_map.write_byte(9, data.get_byte(0));
-                self.bit_map.write_byte(10, data.get_byte(1))
-            }
+            Register::AX => self.general[0] = data as u8,
+            Register::BX => self.general[1] = data as u8,
+            Register::CX => self.general[2] = data as u8,
+            Register::DX => self.general[3] = data as u8,
+            Register::PC => self.pc = data as u16,
+            Register::PCL => self.pc.set_byte(0, data as u8),
+            Register::PCH => self.pc.set_byte(1, data as u8),
+            Register::FLAGS => self.flags = data as u8,
+            Register::STK => self.stk = data as u16,
+            Register::STP => self.stp = data as u16,
         };
     }
     pub fn set_eq(&mut self, val: bool) {
-        self.bit_map.set_bit(FLAGS_OFFSET, val);
+        self.flags.set_bit(FLAG_EQ, val);
     }
     pub fn get_eq(&self) -> bool {
-        return self.bit_map.get_bit(FLAGS_OFFSET);
+        self.flags.get_bit(FLAG_EQ)
     }
     pub fn set_mr(&mut self, val: bool) {
-        self.bit_map.set_bit(FLAGS_OFFSET + 1, val);
+        self.flags.set_bit(FLAG_MR, val);
     }
     pub fn get_mr(&self) -> bool {
-        return self.bit_map.get_bit(FLAGS_OFFSET + 1);
+        self.flags.get_bit(FLAG_MR)
     }
     pub fn set_ls(&mut self, val: bool) {
-        self.bit_map.set_bit(FLAGS_OFFSET + 2, val);
+        self.flags.set_bit(FLAG_LS, val);
     }
     pub fn get_ls(&self) -> bool {
-        return self.bit_map.get_bit(FLAGS_OFFSET + 2);
+        self.flags.get_bit(FLAG_LS)
     }
     pub fn set_zero(&mut self, val: bool) {
-        self.bit_map.set_bit(FLAGS_OFFSET + 3, val);
+        self.flags.set_bit(FLAG_ZERO, val);
     }
     pub fn get_zero(&self) -> bool {
-        return self.bit_map.get_bit(FLAGS_OFFSET + 3);
+        self.flags.get_bit(FLAG_ZERO)
     }
     pub fn set_carry(&mut self, val: bool) {
-        self.bit_map.set_bit(FLAGS_OFFSET + 4, val);
+        self.flags.set_bit(FLAG_CARRY, val);
     }
     pub fn get_carry(&self) -> bool {
-        return self.bit_map.get_bit(FLAGS_OFFSET + 4);
+        self.flags.get_bit(FLAG_CARRY)
+    }
+    pub fn set_interrupt_enable(&mut self, val: bool) {
+        self.flags.set_bit(FLAG_INTERRUPT_ENABLE, val);
+    }
+    pub fn get_interrupt_enable(&self) -> bool {
+        self.flags.get_bit(FLAG_INTERRUPT_ENABLE)
     }
 }
 impl Debug for Registers {
@@ -242,6 +546,17 @@ pub enum Register {
     STP = 8,
     STK = 9,
 }
+impl Register {
+    /// the name used in assambly, its position in `REGISTER_NAMES` is the operand encoding
+    pub fn get_name(&self) -> &'static str {
+        let index = (0..REGISTER_NAMES.len()).find(|&i| Into::<Register>::into(i) == *self);
+        REGISTER_NAMES[index.unwrap_or(0)]
+    }
+    pub fn from_name(name: &str) -> Option<Register> {
+        let index = REGISTER_NAMES.iter().position(|x| *x == name)?;
+        Some(index.into())
+    }
+}
 impl Into<Register> for usize {
     fn into(self) -> Register {
         match self {
@@ -0,0 +1,165 @@
+use std::fmt::Display;
+
+use super::{CPU, STATE_REGISTERS};
+
+/// first bytes of every save state
+pub const SAVE_STATE_MAGIC: [u8; 4] = *b"LUY8";
+/// bumped every time the layout below changes
+///
+/// version 1 layout, all numbers little endian:
+/// - magic and version (u16)
+/// - ax, bx, cx, dx (u8 each), pc (u16), flags (u8), stk (u16), stp (u16)
+/// - halted (u8)
+/// - the data bus: cycles (u64), irq pending, irq mask and nmi (u8 each),
+///   the number of mappings (u16) and for every mapping its base (u32),
+///   length (u32), the size of the driver state (u32) and the driver state
+///
+/// the memory map and the irq lines of devices are not saved, a state only
+/// loads into a machine that was built the same way.
+pub const SAVE_STATE_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// the data ended early
+    Truncated,
+    /// the cpu the state is loaded into has a different memory map
+    MemoryMap {
+        index: usize,
+    },
+    /// a driver could not load its state
+    Driver {
+        index: usize,
+        msg: String,
+    },
+}
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version: {}", version)
+            }
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::MemoryMap { index } => {
+                write!(f, "mapping {} does not match the save state", index)
+            }
+            SaveStateError::Driver { index, msg } => {
+                write!(f, "mapping {} could not be loaded: {}", index, msg)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    pub data: Vec<u8>,
+}
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { data: vec![] }
+    }
+    pub fn write_u8(&mut self, val: u8) {
+        self.data.push(val);
+    }
+    pub fn write_u16(&mut self, val: u16) {
+        self.data.extend(val.to_le_bytes());
+    }
+    pub fn write_u32(&mut self, val: u32) {
+        self.data.extend(val.to_le_bytes());
+    }
+    pub fn write_u64(&mut self, val: u64) {
+        self.data.extend(val.to_le_bytes());
+    }
+    /// write the length (u32) followed by the bytes
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend(bytes);
+    }
+}
+
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(SaveStateError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    /// read bytes written by `StateWriter::write_bytes`
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], SaveStateError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+}
+
+impl CPU {
+    /// the complete machine state, see `SAVE_STATE_VERSION` for the layout
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.data.extend(SAVE_STATE_MAGIC);
+        writer.write_u16(SAVE_STATE_VERSION);
+        for (register, size) in STATE_REGISTERS {
+            let val = self.registers.read_reg(register);
+            match size {
+                1 => writer.write_u8(val as u8),
+                _ => writer.write_u16(val as u16),
+            }
+        }
+        writer.write_u8(self.halted as u8);
+        self.data_bus.save_state(&mut writer);
+        writer.data
+    }
+    /// restore a state made by `save_state`
+    ///
+    /// the drivers are not part of the state, the data bus of this cpu must
+    /// have the same mappings as the one that was saved. the whole state is
+    /// checked first, when it can not be loaded the cpu is left as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(data);
+        if reader.take(SAVE_STATE_MAGIC.len())? != SAVE_STATE_MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = reader.read_u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        let mut registers = vec![];
+        for (register, size) in STATE_REGISTERS {
+            let val = match size {
+                1 => reader.read_u8()? as usize,
+                _ => reader.read_u16()? as usize,
+            };
+            registers.push((register, val));
+        }
+        let halted = reader.read_u8()? != 0;
+        self.data_bus.load_state(&mut reader)?;
+        for (register, val) in registers {
+            self.registers.write_reg(register, val);
+        }
+        self.halted = halted;
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{execution::Fault, CPU};
+
+/// when the host is further behind than this the lost time is dropped
+pub const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// what a throttled run did
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrottleReport {
+    pub cycles: usize,
+    pub elapsed: Duration,
+    /// how often the host could not keep up
+    pub times_behind: usize,
+    pub max_behind: Duration,
+}
+
+/// where a throttle gets the time from
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&mut self, duration: Duration);
+}
+/// the wall clock
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// paces a cpu so it runs at `frequency` cycles per second of wall clock time
+pub struct Throttle {
+    pub frequency: usize,
+    pub clock: Box<dyn Clock>,
+    turbo: Arc<AtomicBool>,
+    origin: Instant,
+    origin_cycles: usize,
+    /// called with the lag every time the host falls behind
+    pub on_behind: Option<Box<dyn FnMut(Duration)>>,
+}
+impl Throttle {
+    /// panics when `frequency` is 0
+    pub fn new(frequency: usize) -> Self {
+        assert!(frequency > 0, "a throttle needs a frequency above 0");
+        Self {
+            frequency,
+            clock: Box::new(SystemClock),
+            turbo: Arc::new(AtomicBool::new(false)),
+            origin: Instant::now(),
+            origin_cycles: 0,
+            on_behind: None,
+        }
+    }
+    /// run as fast as the host can while set
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo.store(turbo, Ordering::Relaxed);
+    }
+    pub fn is_turbo(&self) -> bool {
+        self.turbo.load(Ordering::Relaxed)
+    }
+    /// the turbo flag, to switch it while `run` is going, for example from an input thread
+    pub fn get_turbo_switch(&self) -> Arc<AtomicBool> {
+        self.turbo.clone()
+    }
+    /// pace from `cycles` on, starting now
+    pub fn reset(&mut self, cycles: usize) {
+        self.origin = self.clock.now();
+        self.origin_cycles = cycles;
+    }
+    /// the wall clock time `cycles` cycles take at `frequency`
+    pub fn get_duration(&self, cycles: usize) -> Duration {
+        Duration::from_secs_f64(cycles as f64 / self.frequency as f64)
+    }
+    /// sleep until the cpu may be at `cycles`, returns the lag when the host is behind
+    pub fn pace(&mut self, cycles: usize) -> Option<Duration> {
+        if self.is_turbo() {
+            self.reset(cycles);
+            return None;
+        }
+        let due = self.origin + self.get_duration(cycles.saturating_sub(self.origin_cycles));
+        let now = self.clock.now();
+        if now < due {
+            self.clock.sleep(due - now);
+            return None;
+        }
+        let lag = now - due;
+        if lag < MAX_LAG {
+            return None;
+        }
+        self.reset(cycles);
+        if let Some(on_behind) = &mut self.on_behind {
+            on_behind(lag);
+        }
+        Some(lag)
+    }
+    /// run `cpu` paced until it halts or `predicate` holds after an instruction
+    ///
+    /// the cpu runs in slices of about a millisecond, pacing happens between them.
+    pub fn run_until<F: FnMut(&CPU) -> bool>(
+        &mut self,
+        cpu: &mut CPU,
+        mut predicate: F,
+    ) -> Result<ThrottleReport, Fault> {
+        let start = self.clock.now();
+        let start_cycles = cpu.get_cycles();
+        let slice = (self.frequency / 1000).max(1);
+        let mut report = ThrottleReport {
+            cycles: 0,
+            elapsed: Duration::ZERO,
+            times_behind: 0,
+            max_behind: Duration::ZERO,
+        };
+        self.reset(start_cycles);
+        loop {
+            let slice_end = cpu.get_cycles() + slice;
+            let mut done = false;
+            cpu.run_until(|cpu| {
+                done = predicate(cpu);
+                done || cpu.get_cycles() >= slice_end
+            })?;
+            if let Some(lag) = self.pace(cpu.get_cycles()) {
+                report.times_behind += 1;
+                report.max_behind = report.max_behind.max(lag);
+            }
+            let finished = cpu.is_halted() && !cpu.is_waiting();
+            if done || finished {
+                break;
+            }
+        }
+        report.cycles = cpu.get_cycles() - start_cycles;
+        report.elapsed = self.clock.now() - start;
+        Ok(report)
+    }
+    pub fn run(&mut self, cpu: &mut CPU) -> Result<ThrottleReport, Fault> {
+        self.run_until(cpu, |_| false)
+    }
+}
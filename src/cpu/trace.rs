@@ -0,0 +1,224 @@
+use std::{
+    fmt::Display,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use super::{
+    execution::{Executed, Fault},
+    memory::MemoryWrite,
+    Register, Registers, STATE_REGISTERS,
+};
+
+/// a register that an instruction changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterDelta {
+    pub register: Register,
+    pub old: usize,
+    pub new: usize,
+}
+
+/// everything a single instruction did
+///
+/// written by the cpu as one line of whitespace separated fields, all
+/// numbers in hex:
+/// `pc=0003 op=20 PUSHR 0 0 cycles=3 int=20 pc=3>5 stp=1ff>1fe [01fe]=00>05`
+/// `int` is only there when an interrupt was entered before the instruction,
+/// it is followed by the changed registers and then the memory writes.
+/// a clock that faulted gets `fault=halt`, `fault=brk`, `fault=illegal` or
+/// `fault=bus:00f0` (the addres that failed) right after `int`, a halted cpu
+/// only gets a record for the first clock it waits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    pub pc: usize,
+    pub opcode: u8,
+    pub name: String,
+    pub arguments: (usize, usize),
+    pub cycles: usize,
+    pub interrupt: Option<u8>,
+    pub fault: Option<Fault>,
+    pub registers: Vec<RegisterDelta>,
+    pub writes: Vec<MemoryWrite>,
+}
+impl TraceRecord {
+    pub fn new(
+        executed: &Executed,
+        before: &Registers,
+        after: &Registers,
+        writes: Vec<MemoryWrite>,
+    ) -> Self {
+        let registers = STATE_REGISTERS
+            .into_iter()
+            .filter_map(|(register, _)| {
+                let old = before.read_reg(register.clone());
+                let new = after.read_reg(register.clone());
+                (old != new).then_some(RegisterDelta { register, old, new })
+            })
+            .collect();
+        Self {
+            pc: executed.pc,
+            opcode: executed.opcode,
+            name: executed.name.to_string(),
+            arguments: executed.arguments,
+            cycles: executed.cycles,
+            interrupt: executed.interrupt,
+            fault: None,
+            registers,
+            writes,
+        }
+    }
+}
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pc={:04x} op={:02x} {} {:x} {:x} cycles={:x}",
+            self.pc, self.opcode, self.name, self.arguments.0, self.arguments.1, self.cycles
+        )?;
+        if let Some(vector) = self.interrupt {
+            write!(f, " int={:02x}", vector)?;
+        }
+        match &self.fault {
+            Some(Fault::Halt { .. }) => write!(f, " fault=halt")?,
+            Some(Fault::Breakpoint { .. }) => write!(f, " fault=brk")?,
+            Some(Fault::IllegalOpcode { .. }) => write!(f, " fault=illegal")?,
+            Some(Fault::BusError { addr, .. }) => write!(f, " fault=bus:{:04x}", addr)?,
+            None => {}
+        }
+        for delta in &self.registers {
+            let name = delta.register.get_name();
+            write!(f, " {}={:x}>{:x}", name, delta.old, delta.new)?;
+        }
+        for write in &self.writes {
+            write!(
+                f,
+                " [{:04x}]={:02x}>{:02x}",
+                write.addr, write.old, write.new
+            )?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for TraceRecord {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut fields = line.split_whitespace();
+        let mut next = |name: &str| fields.next().ok_or(format!("missing {}", name));
+        let pc = parse_hex(next("pc")?.strip_prefix("pc=").ok_or("expected pc=")?)?;
+        let opcode = parse_hex(next("opcode")?.strip_prefix("op=").ok_or("expected op=")?)?;
+        let name = next("name")?.to_string();
+        let arguments = (parse_hex(next("argument")?)?, parse_hex(next("argument")?)?);
+        let cycles = next("cycles")?;
+        let cycles = parse_hex(cycles.strip_prefix("cycles=").ok_or("expected cycles=")?)?;
+        let mut record = TraceRecord {
+            pc,
+            opcode: opcode as u8,
+            name,
+            arguments,
+            cycles,
+            interrupt: None,
+            fault: None,
+            registers: vec![],
+            writes: vec![],
+        };
+        for field in fields {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or(format!("invalid field: {}", field))?;
+            if key == "int" {
+                record.interrupt = Some(parse_hex(value)? as u8);
+                continue;
+            }
+            if key == "fault" {
+                record.fault = Some(match value {
+                    "halt" => Fault::Halt { pc },
+                    "brk" => Fault::Breakpoint { pc },
+                    "illegal" => Fault::IllegalOpcode {
+                        pc,
+                        opcode: opcode as u8,
+                    },
+                    _ => match value.strip_prefix("bus:") {
+                        Some(addr) => Fault::BusError {
+                            pc,
+                            addr: parse_hex(addr)?,
+                        },
+                        None => return Err(format!("unknown fault: {}", value)),
+                    },
+                });
+                continue;
+            }
+            let (old, new) = value
+                .split_once('>')
+                .ok_or(format!("invalid change: {}", field))?;
+            let (old, new) = (parse_hex(old)?, parse_hex(new)?);
+            if let Some(addr) = key.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                record.writes.push(MemoryWrite {
+                    addr: parse_hex(addr)?,
+                    old: old as u8,
+                    new: new as u8,
+                });
+            } else if let Some(register) = Register::from_name(key) {
+                record.registers.push(RegisterDelta { register, old, new });
+            } else {
+                return Err(format!("unknown register: {}", key));
+            }
+        }
+        Ok(record)
+    }
+}
+fn parse_hex(text: &str) -> Result<usize, String> {
+    usize::from_str_radix(text, 16).map_err(|_| format!("invalid number: {}", text))
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    /// `line` (starting at 1) is not a valid record
+    Parse {
+        line: usize,
+        msg: String,
+    },
+}
+impl Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceError::Io(err) => write!(f, "{}", err),
+            TraceError::Parse { line, msg } => write!(f, "line {}: {}", line, msg),
+        }
+    }
+}
+
+/// iterates over the records of a trace written by the cpu, empty lines are skipped
+pub struct TraceReader<R: BufRead> {
+    lines: io::Lines<R>,
+    line: usize,
+}
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            lines: input.lines(),
+            line: 0,
+        }
+    }
+}
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line += 1;
+            let text = match self.lines.next()? {
+                Ok(text) => text,
+                Err(err) => return Some(Err(TraceError::Io(err))),
+            };
+            if text.trim().is_empty() {
+                continue;
+            }
+            return Some(text.parse().map_err(|msg| TraceError::Parse {
+                line: self.line,
+                msg,
+            }));
+        }
+    }
+}
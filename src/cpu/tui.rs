@@ -0,0 +1,318 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use super::{
+    debugger::{Debugger, StopReason},
+    Register, STATE_REGISTERS,
+};
+
+/// writes made by this many of the last instructions are highlighted
+const RECENT_WRITES: usize = 16;
+/// cycles run between redraws and key checks while running
+const RUN_SLICE: usize = 20_000;
+/// the panes are laid out for a 80x24 terminal
+const LEFT_WIDTH: usize = 25;
+const RIGHT_COLUMN: usize = 27;
+const RIGHT_WIDTH: usize = 54;
+/// step, next and out, run or pause, step back, toggle a breakpoint at an entered addres,
+/// scroll memory or jump to the last write, quit
+const KEYS: &str = "s/n/o step c run u back b brk j/k/m mem q quit";
+/// ctrl-c, read as a key because raw mode keeps the terminal from sending a signal
+const CTRL_C: u8 = 0x03;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+const ESCAPE: u8 = 0x1B;
+
+/// the console output, kept to draw it after the console was mapped
+#[derive(Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// a full screen debugger drawn with ansi escapes, the terminal itself is up to the caller
+pub struct Tui {
+    pub debugger: Debugger,
+    console: OutputBuffer,
+    memory_start: usize,
+    running: bool,
+    status: String,
+    /// the hex digits typed after `b`, none when not asking for an addres
+    prompt: Option<String>,
+}
+impl Tui {
+    pub fn new(debugger: Debugger, console: OutputBuffer) -> Self {
+        Tui {
+            debugger,
+            console,
+            memory_start: 0,
+            running: false,
+            status: "paused".to_string(),
+            prompt: None,
+        }
+    }
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+    /// handle a key, returns false when the tui should exit
+    pub fn key(&mut self, key: u8) -> bool {
+        if let Some(prompt) = &mut self.prompt {
+            match key {
+                CTRL_C => return false,
+                b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' if prompt.len() < 4 => {
+                    prompt.push(key as char)
+                }
+                BACKSPACE | DELETE => {
+                    prompt.pop();
+                }
+                b'\r' | b'\n' => {
+                    let addr = match prompt.as_str() {
+                        "" => self.debugger.cpu.get_pc(),
+                        text => usize::from_str_radix(text, 16).unwrap(),
+                    };
+                    self.prompt = None;
+                    self.toggle_breakpoint(addr);
+                }
+                ESCAPE => {
+                    self.prompt = None;
+                    self.status = "paused".to_string();
+                }
+                _ => {}
+            }
+            return true;
+        }
+        let reason = match key {
+            b'q' | CTRL_C => return false,
+            b's' => Some(self.debugger.step()),
+            b'n' => Some(self.debugger.step_over()),
+            b'o' => Some(self.debugger.step_out()),
+            b'c' => {
+                self.running = !self.running;
+                self.status = if self.running { "running" } else { "paused" }.to_string();
+                None
+            }
+            b'u' => {
+                let entry = self.debugger.cpu.step_back();
+                self.status = match entry {
+                    Some(_) => "stepped back".to_string(),
+                    None => "no more history".to_string(),
+                };
+                None
+            }
+            b'b' => {
+                self.prompt = Some(String::new());
+                None
+            }
+            b'j' => {
+                self.memory_start = (self.memory_start + 16) & 0xFFFF;
+                None
+            }
+            b'k' => {
+                self.memory_start = self.memory_start.saturating_sub(16);
+                None
+            }
+            b'm' => {
+                if let Some(addr) = self.recent_writes().last() {
+                    self.memory_start = addr & !0xF;
+                }
+                None
+            }
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            self.status = reason.to_string();
+        }
+        true
+    }
+    fn toggle_breakpoint(&mut self, pc: usize) {
+        let existing = self
+            .debugger
+            .get_breakpoints()
+            .iter()
+            .find(|(_, x)| x.pc == Some(pc))
+            .map(|(id, _)| *id);
+        match existing {
+            Some(id) => {
+                self.debugger.remove(id);
+                self.status = format!("removed breakpoint at {:04X}", pc);
+            }
+            None => {
+                self.debugger.add_breakpoint(pc);
+                self.status = format!("breakpoint at {:04X}", pc);
+            }
+        }
+    }
+    /// run for a slice of cycles, stops running on anything but a pause
+    pub fn run_slice(&mut self) {
+        let reason = self.debugger.run_for(RUN_SLICE);
+        if reason != StopReason::Paused {
+            self.running = false;
+            self.status = reason.to_string();
+        }
+    }
+    /// the addresses written by the last instructions, the newest last
+    fn recent_writes(&self) -> Vec<usize> {
+        let Some(history) = &self.debugger.cpu.history else {
+            return vec![];
+        };
+        let entries: Vec<_> = history.iter().rev().take(RECENT_WRITES).collect();
+        entries
+            .iter()
+            .rev()
+            .flat_map(|x| x.writes.iter().map(|x| x.addr))
+            .collect()
+    }
+
+    /// the whole screen, it starts by clearing it
+    pub fn render(&self) -> String {
+        let mut frame = String::from("\x1b[2J");
+        pane(&mut frame, 1, 1, LEFT_WIDTH, "registers", &self.registers());
+        pane(&mut frame, 12, 1, LEFT_WIDTH, "stack", &self.stack());
+        let disassembly = self.disassembly();
+        pane(
+            &mut frame,
+            1,
+            RIGHT_COLUMN,
+            RIGHT_WIDTH,
+            "disassembly",
+            &disassembly,
+        );
+        pane(
+            &mut frame,
+            12,
+            RIGHT_COLUMN,
+            RIGHT_WIDTH,
+            "memory",
+            &self.memory(),
+        );
+        pane(&mut frame, 21, 1, 80, "console", &self.console_lines(2));
+        let status = match &self.prompt {
+            Some(prompt) => format!("break at (enter for pc): {}", prompt),
+            None => self.status.clone(),
+        };
+        let status = truncate(&status, 80 - KEYS.len() - 1);
+        frame += &format!(
+            "\x1b[24;1H\x1b[7m{:w$}\x1b[0m {}",
+            status,
+            KEYS,
+            w = 80 - KEYS.len() - 1
+        );
+        frame
+    }
+    fn registers(&self) -> Vec<String> {
+        let registers = &self.debugger.cpu.registers;
+        let mut lines: Vec<String> = STATE_REGISTERS
+            .into_iter()
+            .map(|(register, size)| {
+                let val = registers.read_reg(register.clone());
+                format!(
+                    "{:6}{:0width$X}",
+                    register.get_name(),
+                    val,
+                    width = size * 2
+                )
+            })
+            .collect();
+        let flags = [
+            ("eq", registers.get_eq()),
+            ("mr", registers.get_mr()),
+            ("ls", registers.get_ls()),
+            ("zero", registers.get_zero()),
+            ("carry", registers.get_carry()),
+            ("ie", registers.get_interrupt_enable()),
+        ];
+        let set: Vec<&str> = flags.iter().filter(|x| x.1).map(|x| x.0).collect();
+        lines.push(format!("[{}]", set.join(" ")));
+        lines.push(format!("cycles {}", self.debugger.cpu.get_cycles()));
+        lines
+    }
+    fn stack(&self) -> Vec<String> {
+        let cpu = &self.debugger.cpu;
+        let stp = cpu.registers.read_reg(Register::STP);
+        let stk = cpu.registers.read_reg(Register::STK);
+        (stp..stp + 8)
+            .map(|addr| {
+                let marker = match addr {
+                    _ if addr == stp => "<- stp",
+                    _ if addr == stk => "<- stk",
+                    _ => "",
+                };
+                let byte = cpu.data_bus.peek_byte(addr & 0xFFFF);
+                format!("{:04X}: {:02X} {}", addr & 0xFFFF, byte, marker)
+            })
+            .collect()
+    }
+    fn disassembly(&self) -> Vec<String> {
+        let pc = self.debugger.cpu.get_pc();
+        let breakpoints: HashSet<usize> = self
+            .debugger
+            .get_breakpoints()
+            .values()
+            .filter_map(|x| x.pc)
+            .collect();
+        let lines = self.debugger.disassemble_around(pc, 3, 5);
+        lines
+            .iter()
+            .map(|(addr, text)| {
+                let marker = if *addr == pc { "=>" } else { "  " };
+                let breakpoint = if breakpoints.contains(addr) { "*" } else { " " };
+                format!("{}{}{:04X}: {}", breakpoint, marker, addr, text)
+            })
+            .collect()
+    }
+    /// 16 bytes per line, bytes written by the last instructions in reverse video
+    fn memory(&self) -> Vec<String> {
+        let recent = self.recent_writes();
+        (0..8)
+            .map(|line| {
+                let start = (self.memory_start + line * 16) & 0xFFFF;
+                let bytes: String = (start..start + 16)
+                    .map(|addr| {
+                        let byte = self.debugger.cpu.data_bus.peek_byte(addr);
+                        match recent.contains(&addr) {
+                            true => format!("\x1b[7m{:02X}\x1b[0m ", byte),
+                            false => format!("{:02X} ", byte),
+                        }
+                    })
+                    .collect();
+                format!("{:04X}: {}", start, bytes)
+            })
+            .collect()
+    }
+    fn console_lines(&self, count: usize) -> Vec<String> {
+        let output = self.console.0.borrow();
+        let text = String::from_utf8_lossy(&output);
+        let lines: Vec<&str> = text.lines().collect();
+        let skip = lines.len().saturating_sub(count);
+        lines[skip..].iter().map(|x| x.to_string()).collect()
+    }
+}
+
+/// draw a titled pane at `row`, `col`, lines are cut to `width` unless they hold escapes
+fn pane(frame: &mut String, row: usize, col: usize, width: usize, title: &str, lines: &[String]) {
+    let title = format!("-- {} ", title);
+    frame.push_str(&format!(
+        "\x1b[{};{}H\x1b[1m{:-<width$}\x1b[0m",
+        row, col, title
+    ));
+    for (i, line) in lines.iter().enumerate() {
+        let line = match line.contains('\x1b') {
+            true => line.clone(),
+            false => truncate(line, width),
+        };
+        frame.push_str(&format!("\x1b[{};{}H{}", row + 1 + i, col, line));
+    }
+}
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
@@ -0,0 +1,149 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{Read, Write},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::cpu::{interrupts::InterruptController, memory::DataDriver, memory::ManipulateBits};
+
+/// writing sends a byte, reading takes the oldest received byte (0 when there is none)
+pub const CONSOLE_DATA: usize = 0;
+/// see the `STATUS_*` bits
+pub const CONSOLE_STATUS: usize = 1;
+/// a received byte can be read from `CONSOLE_DATA`
+pub const STATUS_RX_READY: usize = 0;
+/// a byte can be written to `CONSOLE_DATA`, always set
+pub const STATUS_TX_READY: usize = 1;
+/// raise `rx_irq` while a received byte is waiting, the only writable bit
+pub const STATUS_RX_INTERRUPT: usize = 2;
+
+/// serial console with a data and a status register
+pub struct Console {
+    output: Box<dyn Write>,
+    input: Option<Receiver<u8>>,
+    rx_buffer: RefCell<VecDeque<u8>>,
+    rx_interrupt: bool,
+    /// irq line raised for received bytes when the program enables it
+    ///
+    /// configuration like the memory map, it is not part of the save state.
+    pub rx_irq: Option<usize>,
+}
+impl Console {
+    /// a console that only writes to `output`
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            input: None,
+            rx_buffer: RefCell::new(VecDeque::new()),
+            rx_interrupt: false,
+            rx_irq: None,
+        }
+    }
+    /// a console that also receives everything read from `input`
+    ///
+    /// `input` is read on its own thread so a blocking reader like stdin
+    /// does not stop the cpu.
+    pub fn with_input(output: Box<dyn Write>, mut input: Box<dyn Read + Send>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut byte = [0];
+            while let Ok(1) = input.read(&mut byte) {
+                if sender.send(byte[0]).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut console = Self::new(output);
+        console.input = Some(receiver);
+        console
+    }
+    /// receive `data` as if it was read from the input
+    pub fn push_input(&mut self, data: &[u8]) {
+        self.rx_buffer.borrow_mut().extend(data);
+    }
+    pub fn get_rx_buffer(&self) -> Vec<u8> {
+        self.rx_buffer.borrow().iter().copied().collect()
+    }
+    pub fn get_rx_interrupt(&self) -> bool {
+        self.rx_interrupt
+    }
+    pub fn set_rx_interrupt(&mut self, val: bool) {
+        self.rx_interrupt = val;
+    }
+    fn get_status(&self) -> u8 {
+        let mut status = 0;
+        status.set_bit(STATUS_RX_READY, !self.rx_buffer.borrow().is_empty());
+        status.set_bit(STATUS_TX_READY, true);
+        status.set_bit(STATUS_RX_INTERRUPT, self.rx_interrupt);
+        status
+    }
+}
+impl DataDriver for Console {
+    fn get_size(&self) -> usize {
+        2 * 8
+    }
+
+    fn write_byte(&mut self, addr: usize, data: u8) {
+        match addr {
+            CONSOLE_DATA => {
+                let _ = self.output.write_all(&[data]);
+                let _ = self.output.flush();
+            }
+            CONSOLE_STATUS => self.rx_interrupt = data.get_bit(STATUS_RX_INTERRUPT),
+            _ => {}
+        }
+    }
+
+    fn read_byte(&self, addr: usize) -> u8 {
+        match addr {
+            CONSOLE_DATA => self.rx_buffer.borrow_mut().pop_front().unwrap_or(0),
+            CONSOLE_STATUS => self.get_status(),
+            _ => 0,
+        }
+    }
+
+    fn peek_byte(&self, addr: usize) -> u8 {
+        match addr {
+            CONSOLE_DATA => self.rx_buffer.borrow().front().copied().unwrap_or(0),
+            _ => self.read_byte(addr),
+        }
+    }
+
+    /// sent bytes can not be taken back
+    fn restore_byte(&mut self, addr: usize, data: u8) {
+        if addr != CONSOLE_DATA {
+            self.write_byte(addr, data);
+        }
+    }
+
+    /// the rx interrupt flag followed by the bytes that were not read yet
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.rx_interrupt as u8];
+        state.extend(self.rx_buffer.borrow().iter());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let (rx_interrupt, rx_buffer) = state.split_first().ok_or("empty console state")?;
+        self.rx_interrupt = *rx_interrupt != 0;
+        *self.rx_buffer.borrow_mut() = rx_buffer.iter().copied().collect();
+        Ok(())
+    }
+
+    fn can_interrupt(&self) -> bool {
+        let input = self.input.is_some() || !self.rx_buffer.borrow().is_empty();
+        self.rx_irq.is_some() && self.rx_interrupt && input
+    }
+    fn update(&mut self, _cycles: usize, interrupts: &mut InterruptController) {
+        if let Some(input) = &self.input {
+            self.rx_buffer.borrow_mut().extend(input.try_iter());
+        }
+        if let Some(line) = self.rx_irq {
+            if self.rx_interrupt && !self.rx_buffer.borrow().is_empty() {
+                interrupts.raise(line);
+            }
+        }
+    }
+}
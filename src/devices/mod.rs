@@ -0,0 +1,46 @@
+pub mod console;
+pub mod timer;
+
+use crate::{
+    assambly::Segment,
+    cpu::{
+        memory::{DataBus, DataDriver, RAM},
+        CPU,
+    },
+    MAX_BYTES,
+};
+
+use self::console::Console;
+
+/// the console sits in the i/o page just below the interrupt controller, out of the way of programs
+pub const CONSOLE_BASE: usize = 0xFDFC;
+
+/// a cpu with the segments of `program` loaded, `console` at `CONSOLE_BASE` and ram everywhere else
+pub fn console_machine(program: &[Segment], console: Console) -> Result<CPU, String> {
+    let console_end = CONSOLE_BASE + console.get_size() / 8;
+    for segment in program {
+        if segment.addres < console_end && CONSOLE_BASE < segment.get_end() {
+            return Err(format!(
+                "{} at {:#06X}..{:#06X} overlaps the console at {:#06X}",
+                segment.section.get_name(),
+                segment.addres,
+                segment.get_end(),
+                CONSOLE_BASE
+            ));
+        }
+    }
+    let mut data_bus = DataBus::new();
+    data_bus
+        .map(0, Box::new(RAM::new(CONSOLE_BASE * 8)))
+        .unwrap();
+    data_bus.map(CONSOLE_BASE, Box::new(console)).unwrap();
+    data_bus.add_driver(Box::new(RAM::new((MAX_BYTES - console_end) * 8)));
+    let mut cpu = CPU::new();
+    cpu.mount_data_bus(data_bus);
+    for segment in program {
+        for (i, byte) in segment.bytes.iter().enumerate() {
+            cpu.data_bus.write_byte(segment.addres + i, *byte);
+        }
+    }
+    Ok(cpu)
+}
@@ -0,0 +1,162 @@
+use crate::{
+    cpu::{interrupts::InterruptController, memory::DataDriver, memory::ManipulateBits},
+    utils::SetGetBytes,
+};
+
+/// see the `CONTROL_*` bits, enabling the timer loads the counter from reload
+pub const TIMER_CONTROL: usize = 0;
+/// the counter counts down once every `prescaler + 1` cycles
+pub const TIMER_PRESCALER: usize = 1;
+pub const TIMER_RELOAD_LOW: usize = 2;
+pub const TIMER_RELOAD_HIGH: usize = 3;
+pub const TIMER_COUNTER_LOW: usize = 4;
+pub const TIMER_COUNTER_HIGH: usize = 5;
+/// bit 0 is set when the counter expired, writing a 1 clears it
+pub const TIMER_STATUS: usize = 6;
+pub const CONTROL_ENABLE: usize = 0;
+/// reload and keep counting on expiry instead of stopping
+pub const CONTROL_PERIODIC: usize = 1;
+/// raise `irq` on expiry
+pub const CONTROL_INTERRUPT: usize = 2;
+
+/// a programmable down counter driven by the cpu cycles
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timer {
+    control: u8,
+    prescaler: u8,
+    reload: u16,
+    counter: u16,
+    expired: bool,
+    /// cycles counted towards the next tick
+    cycles: usize,
+    /// configuration like the memory map, it is not part of the save state
+    pub irq: usize,
+}
+impl Timer {
+    pub fn new(irq: usize) -> Self {
+        Self {
+            control: 0,
+            prescaler: 0,
+            reload: 0,
+            counter: 0,
+            expired: false,
+            cycles: 0,
+            irq,
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.control.get_bit(CONTROL_ENABLE)
+    }
+    pub fn get_counter(&self) -> u16 {
+        self.counter
+    }
+    pub fn is_expired(&self) -> bool {
+        self.expired
+    }
+    /// count down `ticks` times, returns true when the counter expired
+    fn tick(&mut self, mut ticks: usize) -> bool {
+        let mut expired = false;
+        while ticks > 0 && self.is_enabled() {
+            if ticks < self.counter as usize {
+                self.counter -= ticks as u16;
+                break;
+            }
+            ticks -= self.counter.max(1) as usize;
+            expired = true;
+            if self.control.get_bit(CONTROL_PERIODIC) && self.reload > 0 {
+                self.counter = self.reload;
+            } else {
+                self.counter = 0;
+                self.control.set_bit(CONTROL_ENABLE, false);
+            }
+        }
+        expired
+    }
+}
+impl DataDriver for Timer {
+    fn get_size(&self) -> usize {
+        7 * 8
+    }
+
+    fn write_byte(&mut self, addr: usize, data: u8) {
+        match addr {
+            TIMER_CONTROL => {
+                if !self.is_enabled() && data.get_bit(CONTROL_ENABLE) {
+                    self.counter = self.reload;
+                    self.cycles = 0;
+                }
+                self.control = data;
+            }
+            TIMER_PRESCALER => self.prescaler = data,
+            TIMER_RELOAD_LOW => self.reload.set_byte(0, data),
+            TIMER_RELOAD_HIGH => self.reload.set_byte(1, data),
+            TIMER_COUNTER_LOW => self.counter.set_byte(0, data),
+            TIMER_COUNTER_HIGH => self.counter.set_byte(1, data),
+            TIMER_STATUS if data.get_bit(0) => self.expired = false,
+            _ => {}
+        }
+    }
+
+    /// put a register back without restarting the counter or clearing the status
+    fn restore_byte(&mut self, addr: usize, data: u8) {
+        match addr {
+            TIMER_CONTROL => self.control = data,
+            TIMER_STATUS => self.expired = data.get_bit(0),
+            _ => self.write_byte(addr, data),
+        }
+    }
+
+    fn read_byte(&self, addr: usize) -> u8 {
+        match addr {
+            TIMER_CONTROL => self.control,
+            TIMER_PRESCALER => self.prescaler,
+            TIMER_RELOAD_LOW => self.reload.get_byte(0),
+            TIMER_RELOAD_HIGH => self.reload.get_byte(1),
+            TIMER_COUNTER_LOW => self.counter.get_byte(0),
+            TIMER_COUNTER_HIGH => self.counter.get_byte(1),
+            TIMER_STATUS => self.expired as u8,
+            _ => 0,
+        }
+    }
+
+    /// the registers followed by the cycles counted towards the next tick
+    fn save_state(&self) -> Vec<u8> {
+        let mut state: Vec<u8> = (0..7).map(|i| self.read_byte(i)).collect();
+        state.extend((self.cycles as u64).to_le_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != 15 {
+            return Err("expected 15 bytes".to_string());
+        }
+        self.control = state[TIMER_CONTROL];
+        self.prescaler = state[TIMER_PRESCALER];
+        self.reload = u16::from_le_bytes([state[TIMER_RELOAD_LOW], state[TIMER_RELOAD_HIGH]]);
+        self.counter = u16::from_le_bytes([state[TIMER_COUNTER_LOW], state[TIMER_COUNTER_HIGH]]);
+        self.expired = state[TIMER_STATUS] != 0;
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&state[7..]);
+        self.cycles = u64::from_le_bytes(cycles) as usize;
+        Ok(())
+    }
+
+    fn can_interrupt(&self) -> bool {
+        self.is_enabled() && self.control.get_bit(CONTROL_INTERRUPT)
+    }
+    fn update(&mut self, cycles: usize, interrupts: &mut InterruptController) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.cycles += cycles;
+        let divider = self.prescaler as usize + 1;
+        let ticks = self.cycles / divider;
+        self.cycles %= divider;
+        if self.tick(ticks) {
+            self.expired = true;
+            if self.control.get_bit(CONTROL_INTERRUPT) {
+                interrupts.raise(self.irq);
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 pub mod assambly;
 pub mod cpu;
+pub mod devices;
 pub mod utils;
 
 pub const MAX_BYTES: usize = 2usize.pow(16);
@@ -7,12 +8,29 @@ pub const RAM_BYTES: usize = MAX_BYTES / 128;
 
 #[cfg(test)]
 mod test {
-    use crate::cpu::memory::{DataBus, RAM};
+    use crate::assambly::assamble;
+    use crate::cpu::execution::Fault;
+    use crate::cpu::interrupts::{
+        IRQ_MASK_ADDRES, IRQ_PENDING_ADDRES, IRQ_VECTOR_BASE, NMI_VECTOR,
+    };
+    use crate::cpu::memory::{DataBus, MapError, OpenBus, RAM, ROM};
+    use crate::cpu::INTERRUPT_VECTOR_TABLE;
     use crate::{
         cpu::{self, CPU},
-        load_bin_file, RAM_BYTES,
+        load_bin_file, MAX_BYTES, RAM_BYTES,
     };
 
+    /// a writer whose output can still be read after it was given away
+    struct Output(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+    impl std::io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
     #[test]
     fn mutlitply_test() {
         let mut cpu = CPU::new();
@@ -29,14 +47,13 @@ mod test {
             let byte = data[i];
             cpu.data_bus.write_byte(i, byte);
         }
-        for _ in 0..21 {
-            cpu.clock();
-        }
+        assert_eq!(cpu.run().unwrap(), 43);
         use cpu::Register;
-        assert_eq!(cpu.registers.read_reg(Register::PC), 100);
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.registers.read_reg(Register::PC), 21);
         assert_eq!(cpu.registers.read_reg(Register::AX), 6);
         assert_eq!(cpu.registers.read_reg(Register::BX), 2);
-        assert_eq!(cpu.registers.read_reg(Register::CX), 5);
+        assert_eq!(cpu.registers.read_reg(Register::CX), 0);
         assert_eq!(cpu.registers.read_reg(Register::STP), 511);
     }
     #[test]
@@ -56,21 +73,1199 @@ mod test {
         }
 
         for _ in 0..20 {
-            cpu.clock();
+            cpu.clock().unwrap();
         }
         use cpu::Register;
         assert_eq!(cpu.registers.read_reg(Register::PC), 48);
     }
+    #[test]
+    fn interrupt_test() {
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(MAX_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("mov ax, 1\nint 5\nmov bx, 2\n".to_string()).unwrap();
+        let handler = assamble("mov cx, 3\niret\n".to_string()).unwrap();
+        for (i, byte) in program.iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        for (i, byte) in handler.iter().enumerate() {
+            cpu.data_bus.write_byte(0x80 + i, *byte);
+        }
+        cpu.data_bus.write_byte(INTERRUPT_VECTOR_TABLE + 5 * 2, 0x00);
+        cpu.data_bus.write_byte(INTERRUPT_VECTOR_TABLE + 5 * 2 + 1, 0x80);
+        cpu.registers.set_carry(true);
+
+        for _ in 0..3 {
+            cpu.clock().unwrap();
+        }
+        use cpu::Register;
+        assert_eq!(cpu.registers.read_reg(Register::PC), 0x83);
+        assert_eq!(cpu.registers.read_reg(Register::STP), RAM_BYTES - 4);
+        cpu.registers.set_carry(false);
+        cpu.clock().unwrap(); // iret
+        assert_eq!(cpu.registers.read_reg(Register::PC), 5);
+        assert!(cpu.registers.get_carry());
+        cpu.clock().unwrap();
+        assert_eq!(cpu.registers.read_reg(Register::AX), 1);
+        assert_eq!(cpu.registers.read_reg(Register::BX), 2);
+        assert_eq!(cpu.registers.read_reg(Register::CX), 3);
+        assert_eq!(cpu.registers.read_reg(Register::STP), RAM_BYTES - 1);
+    }
+    #[test]
+    fn irq_test() {
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(MAX_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("nop\nsti\nnop\nnop\n".to_string()).unwrap();
+        let handler = assamble("add bx, 1\niret\n".to_string()).unwrap();
+        for (i, byte) in program.iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        for (i, byte) in handler.iter().enumerate() {
+            cpu.data_bus.write_byte(0x80 + i, *byte);
+        }
+        for line in 0..2 {
+            let entry = INTERRUPT_VECTOR_TABLE + (IRQ_VECTOR_BASE as usize + line) * 2;
+            cpu.data_bus.write_byte(entry, 0x00);
+            cpu.data_bus.write_byte(entry + 1, 0x80);
+        }
+        use cpu::Register;
+        cpu.data_bus.write_byte(IRQ_MASK_ADDRES, 0b10);
+        // nothing is taken while interrupts are disabled
+        cpu.data_bus.interrupt_controller.raise(0);
+        cpu.data_bus.interrupt_controller.raise(1);
+        cpu.clock().unwrap();
+        assert_eq!(cpu.registers.read_reg(Register::PC), 1);
+        cpu.clock().unwrap(); // sti
+        assert!(cpu.registers.get_interrupt_enable());
+        // line 0 is taken and the first instruction of the handler runs
+        let executed = cpu.clock().unwrap();
+        assert_eq!(executed.interrupt, Some(IRQ_VECTOR_BASE));
+        assert_eq!(executed.pc, 0x80);
+        assert!(!cpu.registers.get_interrupt_enable());
+        cpu.clock().unwrap(); // iret
+        assert_eq!(cpu.registers.read_reg(Register::PC), 2);
+        assert!(cpu.registers.get_interrupt_enable());
+        cpu.clock().unwrap();
+        cpu.clock().unwrap();
+        // line 1 is still masked
+        assert_eq!(cpu.registers.read_reg(Register::PC), 4);
+        assert_eq!(cpu.registers.read_reg(Register::BX), 1);
+        assert_eq!(cpu.data_bus.read_byte(IRQ_PENDING_ADDRES), 0b10);
+        // the nmi ignores the interrupt enable flag
+        cpu.registers.set_interrupt_enable(false);
+        let entry = INTERRUPT_VECTOR_TABLE + NMI_VECTOR as usize * 2;
+        cpu.data_bus.write_byte(entry, 0x00);
+        cpu.data_bus.write_byte(entry + 1, 0x80);
+        cpu.data_bus.interrupt_controller.raise_nmi();
+        let executed = cpu.clock().unwrap();
+        assert_eq!(executed.interrupt, Some(NMI_VECTOR));
+        // an interrupt whose handler faults right away is still reported and counted
+        cpu.data_bus.write_byte(entry + 1, 0x90);
+        cpu.data_bus.write_byte(0x90, 0xFF);
+        cpu.data_bus.interrupt_controller.raise_nmi();
+        let cycles = cpu.get_cycles();
+        let fault = Fault::IllegalOpcode {
+            pc: 0x90,
+            opcode: 0xFF,
+        };
+        assert_eq!(cpu.clock(), Err(fault));
+        assert_eq!(cpu.get_last_interrupt(), Some(NMI_VECTOR));
+        assert_eq!(
+            cpu.get_cycles(),
+            cycles + cpu::instructions::INTERRUPT_CYCLES
+        );
+    }
+    #[test]
+    fn fault_test() {
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("mov ax, 1\nbrk\npush ax\n".to_string()).unwrap();
+        for (i, byte) in program.iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        cpu.data_bus.write_byte(program.len(), 0xFF);
+        let executed = cpu.clock().unwrap();
+        assert_eq!(executed.name, "MOVRV");
+        assert_eq!(executed.cycles, 3);
+        assert_eq!(cpu::instructions::PUSHA.get_cycles(), 5);
+        assert_eq!(cpu::instructions::INTV.get_cycles(), 7);
+        assert_eq!(cpu.clock(), Err(Fault::Breakpoint { pc: 3 }));
+        use cpu::Register;
+        cpu.registers.write_reg(Register::STP, RAM_BYTES + 1);
+        let fault = Fault::BusError {
+            pc: 4,
+            addr: RAM_BYTES,
+        };
+        assert_eq!(cpu.clock(), Err(fault));
+        let fault = Fault::IllegalOpcode {
+            pc: 6,
+            opcode: 0xFF,
+        };
+        assert_eq!(cpu.clock(), Err(fault));
+        assert_eq!(cpu.registers.read_reg(Register::PC), 6);
+    }
+    #[test]
+    fn call_test() {
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(MAX_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("call [0x0400]\nmov bx, 2\n".to_string()).unwrap();
+        let subroutine = assamble("mov ax, 1\nret\n".to_string()).unwrap();
+        for (i, byte) in program.iter().enumerate() {
+            cpu.data_bus.write_byte(0x1F0 + i, *byte);
+        }
+        for (i, byte) in subroutine.iter().enumerate() {
+            cpu.data_bus.write_byte(0x400 + i, *byte);
+        }
+        use cpu::Register;
+        cpu.registers.write_reg(Register::PC, 0x1F0);
+        cpu.clock().unwrap();
+        assert_eq!(cpu.registers.read_reg(Register::PC), 0x400);
+        assert_eq!(cpu.peek_addres(0), 0x1F3);
+        assert_eq!(cpu.data_bus.read_byte(RAM_BYTES - 3), 0xF3);
+        assert_eq!(cpu.data_bus.read_byte(RAM_BYTES - 2), 0x01);
+        cpu.clock().unwrap();
+        cpu.clock().unwrap(); // ret
+        assert_eq!(cpu.registers.read_reg(Register::PC), 0x1F3);
+        assert_eq!(cpu.registers.read_reg(Register::STP), RAM_BYTES - 1);
+        cpu.clock().unwrap();
+        assert_eq!(cpu.registers.read_reg(Register::AX), 1);
+        assert_eq!(cpu.registers.read_reg(Register::BX), 2);
+
+        // a frame across the top of memory
+        cpu.registers.write_reg(Register::STP, 0xFFFF);
+        cpu.data_bus.write_byte(0xFFFF, 0x34);
+        cpu.data_bus.write_byte(0, 0x12);
+        assert_eq!(cpu.peek_addres(0), 0x1234);
+
+        use crate::assambly::{dissassamble_listing, ListingOptions};
+        let options = ListingOptions {
+            origin: 0x1F0,
+            addres: true,
+            bytes: false,
+        };
+        let listing = dissassamble_listing(program, options);
+        assert!(listing.starts_with("call [1024]             ; 01F0: returns to 01F3\n"));
+    }
+    #[test]
+    fn run_test() {
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("loop:\nadd ax, 1\ncmp ax, 10\njne loop\nhlt\n".to_string());
+        for (i, byte) in program.unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        use cpu::Register;
+        cpu.run_until(|cpu| cpu.registers.read_reg(Register::AX) == 4)
+            .unwrap();
+        assert_eq!(cpu.registers.read_reg(Register::AX), 4);
+        assert!(cpu.run_for(10).unwrap() >= 10);
+        assert!(!cpu.is_halted());
+        cpu.run().unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.registers.read_reg(Register::AX), 10);
+        assert_eq!(cpu.clock(), Err(Fault::Halt { pc: 8 }));
+    }
+    #[test]
+    fn memory_map_test() {
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        data_bus
+            .map_mirrored(0x8000, 0x400, Box::new(RAM::new(0x100 * 8)))
+            .unwrap();
+        let overlap = data_bus.map(0x80FF, Box::new(RAM::new(8)));
+        assert_eq!(
+            overlap,
+            Err(MapError::Overlap {
+                base: 0x8000,
+                length: 0x400
+            })
+        );
+        data_bus.add_driver(Box::new(RAM::new(8)));
+        assert_eq!(data_bus.mappings[2].base, 0x8400);
+
+        data_bus.write_byte(0x8001, 0x12);
+        assert_eq!(data_bus.read_byte(0x8101), 0x12);
+        assert_eq!(data_bus.read_byte(0x8301), 0x12);
+        assert_eq!(data_bus.read_byte(0x0001), 0x00);
+        assert_eq!(data_bus.take_error(), None);
+
+        data_bus.read_byte(0x9000);
+        assert_eq!(data_bus.take_error(), Some(0x9000));
+        data_bus.open_bus = OpenBus::Value(0xFF);
+        assert_eq!(data_bus.read_byte(0x9000), 0xFF);
+        assert_eq!(data_bus.take_error(), None);
+        data_bus.open_bus = OpenBus::Log;
+        data_bus.write_byte(0x9001, 0);
+        assert_eq!(data_bus.take_open_bus_log(), vec![0x9001]);
+
+        let past_end = data_bus.map_mirrored(0xFF00, 0x200, Box::new(RAM::new(8)));
+        assert_eq!(
+            past_end,
+            Err(MapError::OutOfRange {
+                base: 0xFF00,
+                length: 0x200
+            })
+        );
+        let wrapping = data_bus.map_mirrored(usize::MAX, 2, Box::new(RAM::new(8)));
+        assert!(matches!(wrapping, Err(MapError::OutOfRange { .. })));
+        let irq = data_bus.map(0xFDFF, Box::new(RAM::new(8)));
+        assert_eq!(
+            irq,
+            Err(MapError::Overlap {
+                base: 0xFDFE,
+                length: 2
+            })
+        );
+    }
+    #[test]
+    fn registers_test() {
+        use cpu::{Register, Registers};
+        let mut registers = Registers::new();
+        registers.write_reg(Register::PC, 0x1234);
+        assert_eq!(registers.read_reg(Register::PCL), 0x34);
+        assert_eq!(registers.read_reg(Register::PCH), 0x12);
+        registers.write_reg(Register::PCH, 0xAB);
+        assert_eq!(registers.read_reg(Register::PC), 0xAB34);
+        registers.write_reg(Register::AX, 0x1FF);
+        assert_eq!(registers.read_reg(Register::AX), 0xFF);
+        registers.set_eq(true);
+        registers.set_carry(true);
+        assert_eq!(registers.read_reg(Register::FLAGS), 0b1000_1000);
+        registers.write_reg(Register::FLAGS, 0b0001_0100);
+        assert!(registers.get_zero() && registers.get_interrupt_enable());
+        assert!(!registers.get_eq());
+    }
+    #[test]
+    fn rom_test() {
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        let program = assamble("mov ax, 7\nstr ax, 1\nhlt\n".to_string()).unwrap();
+        data_bus.map_rom(0, program).unwrap();
+        cpu.mount_data_bus(data_bus);
+        cpu.run().unwrap();
+        assert_eq!(cpu.data_bus.read_byte(1), 0);
+
+        let mut rom = ROM::new(vec![0; 4]);
+        rom.fault_on_write = true;
+        let mut data_bus = DataBus::new();
+        data_bus.map(0, Box::new(rom)).unwrap();
+        data_bus.write_byte(2, 1);
+        assert_eq!(data_bus.take_error(), Some(2));
+        assert_eq!(data_bus.read_byte(2), 0);
+    }
+    #[test]
+    fn console_test() {
+        use crate::devices::console::{Console, CONSOLE_STATUS};
+        use crate::devices::{console_machine, CONSOLE_BASE};
+        use std::{cell::RefCell, rc::Rc};
+        let output = Rc::new(RefCell::new(vec![]));
+        let mut console = Console::new(Box::new(Output(output.clone())));
+        console.rx_irq = Some(3);
+        console.push_input(b"xy");
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.map(0, Box::new(RAM::new(0xF0 * 8))).unwrap();
+        data_bus.map(0xF0, Box::new(console)).unwrap();
+        cpu.mount_data_bus(data_bus);
+        let program = "mov ax, 104\nstr ax, 240\nmov ax, [0xF0]\nstr ax, 240\nhlt\n";
+        for (i, byte) in assamble(program.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        assert_eq!(cpu.data_bus.read_byte(0xF0 + CONSOLE_STATUS), 0b011);
+        cpu.run().unwrap();
+        assert_eq!(output.borrow().as_slice(), b"hx");
+        assert_eq!(cpu.data_bus.peek_byte(0xF0), b'y');
+        cpu.data_bus.update(1);
+        assert_eq!(cpu.data_bus.interrupt_controller.get_pending(), 0);
+        cpu.data_bus.write_byte(0xF0 + CONSOLE_STATUS, 0b100);
+        cpu.data_bus.update(1);
+        assert_eq!(cpu.data_bus.interrupt_controller.get_pending(), 0b1000);
+        assert_eq!(cpu.data_bus.read_byte(0xF0 + CONSOLE_STATUS), 0b111);
+
+        // programs larger than the zero page run with the console mapped
+        let output = Rc::new(RefCell::new(vec![]));
+        let console = Console::new(Box::new(Output(output.clone())));
+        let code = ".fill 0x100\nhlt\n".to_string();
+        let segments = crate::assambly::assamble_segments(code).unwrap();
+        let mut cpu = console_machine(&segments, console).unwrap();
+        cpu.run().unwrap();
+        cpu.data_bus.write_byte(CONSOLE_BASE, b'k');
+        assert_eq!(output.borrow().as_slice(), b"k");
+    }
+    #[test]
+    fn timer_test() {
+        use crate::cpu::interrupts::InterruptController;
+        use crate::cpu::memory::DataDriver;
+        use crate::devices::timer::*;
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.map(0, Box::new(RAM::new(0xE0 * 8))).unwrap();
+        data_bus.map(0xE0, Box::new(Timer::new(0))).unwrap();
+        data_bus.map(0x100, Box::new(RAM::new(0xFCFE * 8))).unwrap();
+        data_bus.map(0xFE00, Box::new(RAM::new(0x200 * 8))).unwrap();
+        cpu.mount_data_bus(data_bus);
+        cpu.registers.write_reg(cpu::Register::STP, 0x1FF);
+        // reload 10, periodic with interrupt, wait for interrupts in a loop
+        let program =
+            "mov ax, 10\nstr ax, 226\nmov ax, 7\nstr ax, 224\nsti\nloop:\nhlt\njmp loop\n";
+        for (i, byte) in assamble(program.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        let handler = "add bx, 1\nmov ax, 1\nstr ax, 230\niret\n";
+        for (i, byte) in assamble(handler.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(0x80 + i, *byte);
+        }
+        let entry = INTERRUPT_VECTOR_TABLE + IRQ_VECTOR_BASE as usize * 2;
+        cpu.data_bus.write_byte(entry, 0x00);
+        cpu.data_bus.write_byte(entry + 1, 0x80);
+
+        use cpu::Register;
+        let cycles = cpu
+            .run_until(|cpu| cpu.registers.read_reg(Register::BX) == 3)
+            .unwrap();
+        assert_eq!(cycles, 66);
+        assert_eq!(cpu.get_cycles(), 66);
+        assert!(cpu.data_bus.read_byte(0xE0 + TIMER_COUNTER_LOW) <= 10);
+        // once the timer is stopped nothing can wake the halted cpu anymore
+        cpu.data_bus.write_byte(0xE0 + TIMER_CONTROL, 0);
+        cpu.run().unwrap();
+        assert!(cpu.is_halted() && !cpu.is_waiting());
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        for (i, byte) in assamble("sti\nhlt\n".to_string())
+            .unwrap()
+            .iter()
+            .enumerate()
+        {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        cpu.run().unwrap();
+        assert!(cpu.is_halted() && cpu.registers.get_interrupt_enable());
+
+        let mut timer = Timer::new(0);
+        let mut interrupts = InterruptController::new();
+        timer.write_byte(TIMER_PRESCALER, 1);
+        timer.write_byte(TIMER_RELOAD_LOW, 3);
+        timer.write_byte(TIMER_CONTROL, 0b001);
+        timer.update(5, &mut interrupts);
+        assert_eq!(timer.get_counter(), 1);
+        timer.update(2, &mut interrupts);
+        assert!(timer.is_expired() && !timer.is_enabled());
+        assert_eq!(interrupts.get_pending(), 0);
+    }
+    #[test]
+    fn throttle_test() {
+        use crate::cpu::throttle::{Clock, Throttle};
+        use std::sync::atomic::Ordering;
+        use std::time::{Duration, Instant};
+        /// a clock that only moves when the throttle sleeps
+        struct FakeClock(Instant, Duration);
+        impl Clock for FakeClock {
+            fn now(&self) -> Instant {
+                self.0 + self.1
+            }
+            fn sleep(&mut self, duration: Duration) {
+                self.1 += duration;
+            }
+        }
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("loop:\nadd ax, 1\ncmp ax, 100\njne loop\nhlt\n".to_string());
+        for (i, byte) in program.unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        // 100 loops of 8 cycles at 20 kHz take 40ms, plus hlt and the clock that saw the halt
+        assert!(std::panic::catch_unwind(|| Throttle::new(0)).is_err());
+        let mut throttle = Throttle::new(20_000);
+        throttle.clock = Box::new(FakeClock(Instant::now(), Duration::ZERO));
+        let report = throttle.run(&mut cpu).unwrap();
+        assert_eq!(report.cycles, 802);
+        assert_eq!(report.elapsed, throttle.get_duration(802));
+        assert_eq!(report.times_behind, 0);
+        assert_eq!(throttle.get_duration(20_000), Duration::from_secs(1));
+
+        cpu.registers.write_reg(cpu::Register::PC, 0);
+        cpu.registers.write_reg(cpu::Register::AX, 0);
+        cpu.resume();
+        throttle.get_turbo_switch().store(true, Ordering::Relaxed);
+        assert!(throttle.is_turbo());
+        let report = throttle.run(&mut cpu).unwrap();
+        assert_eq!(report.elapsed, Duration::ZERO);
+    }
+    #[test]
+    fn save_state_test() {
+        use crate::cpu::save_state::SaveStateError;
+        use crate::devices::timer::*;
+        fn machine() -> CPU {
+            let mut cpu = CPU::new();
+            let mut data_bus = DataBus::new();
+            data_bus.map(0, Box::new(RAM::new(0xE0 * 8))).unwrap();
+            data_bus.map(0xE0, Box::new(Timer::new(0))).unwrap();
+            data_bus.map(0x100, Box::new(RAM::new(0xFCFE * 8))).unwrap();
+            data_bus.map(0xFE00, Box::new(RAM::new(0x200 * 8))).unwrap();
+            cpu.mount_data_bus(data_bus);
+            cpu
+        }
+        let mut cpu = machine();
+        let program = "mov ax, 200\nstr ax, 226\nmov ax, 1\nstr ax, 224\n\
+            loop:\nadd bx, 3\nsub cx, 1\ncmp cx, 0\njne loop\nhlt\n";
+        for (i, byte) in assamble(program.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        cpu.data_bus.interrupt_controller.set_mask(0b10);
+        cpu.run_for(300).unwrap();
+        let state = cpu.save_state();
+
+        let mut restored = machine();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.registers, cpu.registers);
+        assert_eq!(restored.get_cycles(), cpu.get_cycles());
+        assert_eq!(restored.data_bus.interrupt_controller.get_mask(), 0b10);
+        assert_eq!(restored.save_state(), state);
+        cpu.run().unwrap();
+        restored.run().unwrap();
+        assert_eq!(restored.registers, cpu.registers);
+        assert_eq!(restored.get_cycles(), cpu.get_cycles());
+        assert_eq!(
+            restored.data_bus.peek_byte(0xE0 + TIMER_COUNTER_LOW),
+            cpu.data_bus.peek_byte(0xE0 + TIMER_COUNTER_LOW)
+        );
+
+        assert_eq!(restored.load_state(b"LUY9"), Err(SaveStateError::BadMagic));
+        // a state that is cut off changes nothing
+        let before = restored.save_state();
+        assert_ne!(before, state);
+        assert_eq!(
+            restored.load_state(&state[..state.len() - 1]),
+            Err(SaveStateError::Truncated)
+        );
+        assert_eq!(restored.save_state(), before);
+        assert_eq!(restored.registers, cpu.registers);
+        let mut other = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        other.mount_data_bus(data_bus);
+        assert_eq!(
+            other.load_state(&state),
+            Err(SaveStateError::MemoryMap { index: 0 })
+        );
+
+        use crate::cpu::memory::DataDriver;
+        let mut rom = ROM::new(vec![1, 2]);
+        assert!(rom.load_state(&[3, 4, 5]).is_err());
+        assert_eq!(rom.load_state(&[3, 4]), Ok(()));
+        assert_eq!(rom.read_byte(1), 4);
+    }
+    #[test]
+    fn trace_test() {
+        use crate::cpu::memory::MemoryWrite;
+        use crate::cpu::trace::{TraceError, TraceReader, TraceRecord};
+        use cpu::Register;
+        use std::{cell::RefCell, rc::Rc};
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("mov ax, 5\npush ax\nadd ax, 1\nhlt\n".to_string());
+        for (i, byte) in program.unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        let output = Rc::new(RefCell::new(vec![]));
+        cpu.trace = Some(Box::new(Output(output.clone())));
+        cpu.run().unwrap();
+        let text = String::from_utf8(output.borrow().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[1],
+            "pc=0003 op=20 PUSHR 0 0 cycles=3 pc=3>5 stp=1ff>1fe [01fe]=00>05"
+        );
+        assert_eq!(lines[4], "pc=0008 op=4c HLT 0 0 cycles=1 fault=halt");
+
+        let records: Vec<TraceRecord> = TraceReader::new(text.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records[0].name, "MOVRV");
+        assert_eq!(records[0].arguments, (0, 5));
+        assert_eq!(records[1].registers[1].register, Register::STP);
+        assert_eq!(
+            records[1].writes,
+            vec![MemoryWrite {
+                addr: 0x1FE,
+                old: 0,
+                new: 5
+            }]
+        );
+        assert_eq!(records[2].registers[0].new, 6);
+        assert_eq!(records[3].name, "HLT");
+        assert_eq!(records[4].fault, Some(Fault::Halt { pc: 8 }));
+        for (record, line) in records.iter().zip(&lines) {
+            assert_eq!(&record.to_string(), line);
+        }
+
+        // waiting in the halt is only traced once
+        cpu.clock().unwrap_err();
+        assert_eq!(output.borrow().len(), text.len());
+
+        // faults leave a record too
+        cpu.data_bus.write_byte(0x100, 0xFF);
+        cpu.resume();
+        cpu.registers.write_reg(Register::PC, 0x100);
+        let output = Rc::new(RefCell::new(vec![]));
+        cpu.trace = Some(Box::new(Output(output.clone())));
+        let fault = cpu.clock().unwrap_err();
+        let text = String::from_utf8(output.borrow().clone()).unwrap();
+        assert_eq!(text, "pc=0100 op=ff ??? 0 0 cycles=0 fault=illegal\n");
+        let record: TraceRecord = text.trim().parse().unwrap();
+        assert_eq!(record.fault, Some(fault));
+        let mut cpu = CPU::new();
+        cpu.mount_data_bus(DataBus::new());
+        let output = Rc::new(RefCell::new(vec![]));
+        cpu.trace = Some(Box::new(Output(output.clone())));
+        cpu.clock().unwrap_err();
+        let text = String::from_utf8(output.borrow().clone()).unwrap();
+        assert!(text.contains("fault=bus:0000"), "{}", text);
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("mov ax, [0x9000]\n".to_string()).unwrap();
+        for (i, byte) in program.iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        let output = Rc::new(RefCell::new(vec![]));
+        cpu.trace = Some(Box::new(Output(output.clone())));
+        cpu.clock().unwrap_err();
+        let text = String::from_utf8(output.borrow().clone()).unwrap();
+        assert_eq!(
+            text,
+            "pc=0000 op=18 MOVRA 0 9000 cycles=0 fault=bus:9000 pc=0>4\n"
+        );
+
+        let mut reader = TraceReader::new("\npc=0000 op=00 nop 0 0 cycles=1 qx=1>2\n".as_bytes());
+        match reader.next() {
+            Some(Err(TraceError::Parse { line: 2, .. })) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(reader.next().is_none());
+    }
+    #[test]
+    fn history_test() {
+        use cpu::Register;
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = "mov ax, 7\nstr ax, 100\nmov ax, 9\npush ax\nstr ax, 100\nadd ax, 1\nhlt\n";
+        for (i, byte) in assamble(program.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        assert_eq!(cpu.step_back(), None);
+        cpu.enable_history(4);
+        cpu.run().unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.history.as_ref().unwrap().len(), 4);
+        let registers = cpu.registers.clone();
+
+        let entry = cpu.step_back().unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.get_pc(), entry.get_pc());
+        assert_eq!(cpu.registers.read_reg(Register::AX), 10);
+        cpu.step_back().unwrap();
+        assert_eq!(cpu.registers.read_reg(Register::AX), 9);
+
+        // the str ax, 100 that wrote 9 is taken back, the one that wrote 7 was dropped
+        let entry = cpu.run_back_to_write(100).unwrap();
+        assert_eq!(entry.writes[0].old, 7);
+        assert_eq!(cpu.data_bus.read_byte(100), 7);
+        assert_eq!(cpu.data_bus.read_byte(RAM_BYTES - 2), 9);
+        assert_eq!(cpu.run_back_to_write(100), None);
+        assert_eq!(cpu.data_bus.read_byte(RAM_BYTES - 2), 0);
+        assert_eq!(cpu.registers.read_reg(Register::STP), RAM_BYTES - 1);
+
+        cpu.run().unwrap();
+        assert_eq!(cpu.registers, registers);
+        assert_eq!(cpu.data_bus.read_byte(100), 9);
+
+        // the interrupt controller and the timer go back without side effects
+        use crate::cpu::memory::DataDriver;
+        use crate::devices::timer::*;
+        let mut data_bus = DataBus::new();
+        data_bus.interrupt_controller.raise(1);
+        data_bus.start_write_log();
+        data_bus.write_byte(IRQ_MASK_ADDRES, 0b10);
+        data_bus.write_byte(IRQ_PENDING_ADDRES, 0b10);
+        let writes = data_bus.take_write_log();
+        assert_eq!(writes.len(), 2);
+        for write in writes.iter().rev() {
+            data_bus.restore_byte(write.addr, write.old);
+        }
+        assert_eq!(data_bus.interrupt_controller.get_mask(), 0);
+        assert_eq!(data_bus.interrupt_controller.get_pending(), 0b10);
+        let mut timer = Timer::new(0);
+        timer.write_byte(TIMER_RELOAD_LOW, 5);
+        timer.write_byte(TIMER_CONTROL, 1);
+        timer.write_byte(TIMER_COUNTER_LOW, 2);
+        timer.write_byte(TIMER_CONTROL, 0);
+        timer.restore_byte(TIMER_CONTROL, 1);
+        assert!(timer.is_enabled());
+        assert_eq!(timer.get_counter(), 2);
+    }
+    #[test]
+    fn debugger_test() {
+        use crate::cpu::debugger::*;
+        use cpu::Register;
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = "call [0x0040]\nmov bx, 2\nstr bx, 100\nmov cx, [0x0064]\nhlt\n";
+        for (i, byte) in assamble(program.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        let function = "mov ax, 1\nadd ax, 1\nret\n";
+        for (i, byte) in assamble(function.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(0x40 + i, *byte);
+        }
+        let mut debugger = Debugger::new(cpu);
+        let function = debugger.add_breakpoint(0x40);
+        assert_eq!(
+            debugger.run(),
+            StopReason::Breakpoint {
+                id: function,
+                pc: 0x40
+            }
+        );
+        assert_eq!(debugger.step(), StopReason::Step);
+        assert_eq!(debugger.cpu.get_pc(), 0x43);
+        assert_eq!(debugger.step_out(), StopReason::Step);
+        assert_eq!(debugger.cpu.get_pc(), 3);
+        assert_eq!(debugger.cpu.registers.read_reg(Register::AX), 2);
+
+        let write = debugger.add_watchpoint(100..101, WatchKind::Write);
+        let read = debugger.add_watchpoint(96..104, WatchKind::Read);
+        let stop = debugger.run();
+        assert_eq!(
+            stop,
+            StopReason::Watchpoint {
+                id: write,
+                pc: 6,
+                addr: 100,
+                write: true
+            }
+        );
+        let stop = debugger.run();
+        assert_eq!(
+            stop,
+            StopReason::Watchpoint {
+                id: read,
+                pc: 9,
+                addr: 100,
+                write: false
+            }
+        );
+        assert_eq!(debugger.run(), StopReason::Halted);
+
+        debugger.cpu.resume();
+        debugger.cpu.registers.write_reg(Register::PC, 0);
+        assert!(debugger.remove(function) && debugger.remove(write) && debugger.remove(read));
+        assert!(!debugger.remove(read));
+        assert_eq!(debugger.step_over(), StopReason::Step);
+        assert_eq!(debugger.cpu.get_pc(), 3);
+
+        debugger.cpu.registers.write_reg(Register::PC, 0);
+        let condition = Condition {
+            register: Register::AX,
+            comparison: Comparison::Equal,
+            value: 1,
+        };
+        let id = debugger.add_conditional_breakpoint(None, Some(condition));
+        assert_eq!(debugger.run(), StopReason::Breakpoint { id, pc: 0x43 });
+        assert_eq!(debugger.run_for(1), StopReason::Paused);
+
+        assert_eq!(debugger.disassemble(0), Some(("call [64]".to_string(), 3)));
+        let lines = debugger.disassemble_around(3, 2, 1);
+        assert_eq!(lines[0], (0, "call [64]".to_string()));
+        assert_eq!(lines[2], (6, "str bx, 100".to_string()));
+        assert_eq!(lines.len(), 3);
+        debugger.cpu.data_bus.write_byte(0x20, 0xFF);
+        assert_eq!(debugger.disassemble(0x20), None);
+        assert_eq!(debugger.disassemble_around(0x20, 0, 0)[0].1, "db 0xFF");
+        let dump = debugger.hexdump(0x40, 4);
+        assert_eq!(dump, vec![format!("0040: {:47} |....|", "16 00 01 01")]);
+
+        // the function pushed something and calls another one before stepping out
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program =
+            "call [0x0040]\nhlt\n.org 0x40\npush ax\ncall [0x0050]\npop ax\nret\n.org 0x50\nret\n";
+        for (i, byte) in assamble(program.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        let mut debugger = Debugger::new(cpu);
+        debugger.add_breakpoint(0x40);
+        debugger.run();
+        assert_eq!(debugger.step(), StopReason::Step);
+        assert_eq!(debugger.step_out(), StopReason::Step);
+        assert_eq!(debugger.cpu.get_pc(), 3);
+
+        // stepping over a call in the last bytes of memory
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(MAX_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = "hlt\n.org 0x40\nret\n.org 0xFFFD\ncall [0x0040]\n";
+        for (i, byte) in assamble(program.to_string()).unwrap().iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        cpu.registers.write_reg(Register::PC, 0xFFFD);
+        let mut debugger = Debugger::new(cpu);
+        assert_eq!(debugger.step_over(), StopReason::Step);
+        assert_eq!(debugger.cpu.get_pc(), 0);
+        assert_eq!(debugger.hexdump(0xFFF0, 0x100).len(), 1);
+        assert_eq!(debugger.hexdump(0xFFF0, usize::MAX).len(), 1);
+
+        // debug writes have no side effects
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        data_bus.map_rom(0x1000, vec![1]).unwrap();
+        data_bus.start_write_log();
+        data_bus.poke_byte(0, 7);
+        data_bus.poke_byte(0x1000, 7);
+        data_bus.poke_byte(0x2000, 7);
+        assert_eq!(data_bus.peek_byte(0), 7);
+        assert_eq!(data_bus.peek_byte(0x1000), 1);
+        assert_eq!(data_bus.take_error(), None);
+        assert!(data_bus.take_write_log().is_empty());
+    }
+    #[test]
+    fn gdb_test() {
+        use crate::cpu::debugger::Debugger;
+        use crate::cpu::gdb::GdbStub;
+        use std::{cell::RefCell, io::Cursor, rc::Rc};
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("mov bx, 2\nmov cx, 3\nhlt\n".to_string()).unwrap();
+        for (i, byte) in program.iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        let packets = [
+            "qSupported:swbreak+",
+            "qXfer:features:read:target.xml:0,fff",
+            "g",
+            "P0=2a",
+            "p0",
+            "M64,2:0102",
+            "m64,2",
+            "Z0,6,1",
+            "c",
+            "p4",
+            "s",
+            "z0,6,1",
+            "c",
+            "mffff,ffffffffffffffff",
+            "M10000,1:00",
+            "qXfer:features:read:target.xml:1,ffffffffffffffff",
+            "Z2,ffff,10",
+            "D",
+        ];
+        let input: String = packets
+            .iter()
+            .map(|x| {
+                let checksum = x.bytes().fold(0u8, |sum, x| sum.wrapping_add(x));
+                format!("+${}#{:02x}", x, checksum)
+            })
+            .collect();
+        let output = Rc::new(RefCell::new(vec![]));
+        let mut stub = GdbStub::new(
+            Debugger::new(cpu),
+            Box::new(Cursor::new(input.into_bytes())),
+            Box::new(Output(output.clone())),
+        );
+        stub.serve().unwrap();
+        let output = String::from_utf8(output.borrow().clone()).unwrap();
+        let replies: Vec<&str> = output
+            .split('$')
+            .skip(1)
+            .map(|x| x.split('#').next().unwrap())
+            .collect();
+        assert!(output.starts_with('+'));
+        assert!(replies[0].contains("qXfer:features:read+"));
+        assert!(replies[1].starts_with("l<?xml"));
+        assert!(replies[1].contains("<reg name=\"stp\" bitsize=\"16\""));
+        assert_eq!(replies[2], "00000000000000ff01ff01");
+        assert_eq!(&replies[3..7], ["OK", "2a", "OK", "0102"]);
+        assert_eq!(stub.debugger.cpu.data_bus.read_byte(100), 1);
+        assert_eq!(
+            &replies[7..],
+            [
+                "OK",
+                "T05swbreak:;",
+                "0600",
+                "S05",
+                "OK",
+                "S05",
+                "E01",
+                "E01",
+                "E01",
+                "E01",
+                "OK"
+            ]
+        );
+        assert!(stub.debugger.cpu.is_halted());
+
+        // an access watchpoint is reported as awatch
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("mov bx, [0x0064]\nhlt\n".to_string()).unwrap();
+        for (i, byte) in program.iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        let input: String = ["Z4,64,1", "c", "D"]
+            .iter()
+            .map(|x| {
+                let checksum = x.bytes().fold(0u8, |sum, x| sum.wrapping_add(x));
+                format!("+${}#{:02x}", x, checksum)
+            })
+            .collect();
+        let output = Rc::new(RefCell::new(vec![]));
+        let mut stub = GdbStub::new(
+            Debugger::new(cpu),
+            Box::new(Cursor::new(input.into_bytes())),
+            Box::new(Output(output.clone())),
+        );
+        stub.serve().unwrap();
+        let output = String::from_utf8(output.borrow().clone()).unwrap();
+        assert!(output.contains("$T05awatch:64;#"));
+    }
+    #[test]
+    fn tui_test() {
+        use crate::cpu::debugger::Debugger;
+        use crate::cpu::tui::{OutputBuffer, Tui};
+        use std::io::Write;
+        let mut cpu = CPU::new();
+        let mut data_bus = DataBus::new();
+        data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
+        cpu.mount_data_bus(data_bus);
+        let program = assamble("mov bx, 2\nhlt\n".to_string()).unwrap();
+        for (i, byte) in program.iter().enumerate() {
+            cpu.data_bus.write_byte(i, *byte);
+        }
+        let mut console = OutputBuffer::default();
+        console.write_all(b"hello\n").unwrap();
+        let mut tui = Tui::new(Debugger::new(cpu), console);
+        let frame = tui.render();
+        assert!(frame.starts_with("\x1b[2J"));
+        assert!(frame.contains("\x1b[1;27H\x1b[1m-- disassembly ---"));
+        assert!(frame.contains("\x1b[2;27H =>0000: mov bx, 2"));
+        assert!(frame.contains("\x1b[2;1Hax    00"));
+        assert!(frame.contains("\x1b[13;27H0000: 16 01 02 4C "));
+        assert!(frame.contains("\x1b[22;1Hhello"));
+        assert!(frame.contains("\x1b[7mpaused "));
+
+        // a breakpoint at an entered addres
+        for key in b"b3" {
+            assert!(tui.key(*key));
+        }
+        assert!(tui.render().contains("break at (enter for pc): 3"));
+        assert!(tui.key(b'\r'));
+        let frame = tui.render();
+        assert!(frame.contains("*  0003: hlt"));
+        assert!(frame.contains("breakpoint at 0003"));
+        assert!(!tui.key(b'q'));
+    }
+    #[test]
+    fn assambler_errors_test() {
+        let code = "start:\n  add ax, [qx+1]\n\tjmp nowhere\nstart: add ax, 300\nmov ax,\n";
+        let errors = assamble(code.to_string()).unwrap_err();
+        let found: Vec<(usize, usize, usize)> = errors
+            .errors
+            .iter()
+            .map(|x| x.get_span().unwrap())
+            .map(|x| (x.line, x.column, x.length))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(2, 12, 2), (3, 6, 7), (4, 1, 5), (4, 16, 3), (5, 7, 1)]
+        );
+        assert_eq!(errors.errors[0].get_msg(), "invalid register: qx");
+        let text = errors.with_file("main.asm".to_string()).to_string();
+        assert!(text.starts_with(
+            "main.asm:2:12: error: invalid register: qx\n   2 |   add ax, [qx+1]\n     |            ^^\n"
+        ));
+        assert!(text.contains("main.asm:3:6: error: undefined label: nowhere\n   3 | \tjmp nowhere\n     | \t    ^^^^^^^"));
+        assert!(assamble("mov ax, 0x10\nloop: jmp loop\n".to_string()).is_ok());
+        let errors = assamble("mov qx, 1\n".to_string()).unwrap_err();
+        assert_eq!(errors.errors[0].get_msg(), "invalid register: qx");
+        assert!(assamble("qx: mov ax, qx\n".to_string()).is_ok());
+    }
+    #[test]
+    fn comments_test() {
+        use crate::assambly::{dissassamble_listing, ListingOptions};
+        let code = "; counts to 3\nloop: add ax, 1 // one more\n/* compare\n   with 3 */ cmp ax, 3\njne loop ; again\nhlt /* done */\n";
+        let program = assamble(code.to_string()).unwrap();
+        assert_eq!(
+            program,
+            assamble("loop: add ax, 1\ncmp ax, 3\njne loop\nhlt\n".to_string()).unwrap()
+        );
+        let options = ListingOptions {
+            origin: 0x100,
+            addres: true,
+            bytes: true,
+        };
+        let listing = dissassamble_listing(program.clone(), options);
+        assert!(listing.starts_with("add ax, 1               ; 0100: 01 00 01\n"));
+        assert!(listing.ends_with(
+            "jne 0                   ; 0106: 33 00\nhlt                     ; 0108: 4C\n"
+        ));
+        assert_eq!(assamble(listing).unwrap(), program);
+        // data after the code and an instruction that is cut off
+        let mut image = program.clone();
+        image.extend([0xFF, 0x01]);
+        let listing = dissassamble_listing(image.clone(), options);
+        assert!(listing
+            .ends_with(".db 0xFF                ; 0109: FF\n.db 0x01                ; 010A: 01\n"));
+        assert_eq!(assamble(listing).unwrap(), image);
+        let errors = assamble("nop /* never closed\nnop\n".to_string()).unwrap_err();
+        assert_eq!(errors.errors[0].get_span().map(|x| x.column), Some(5));
+    }
+    #[test]
+    fn directives_test() {
+        let code = r#"
+            jmp start
+        table: .db 1, 2, 0x10, "Hi"
+        words: .dw 0x1234, table
+        .align 4
+        text: .string "a\"b\n"
+        buffer: .fill 3, 255
+        start: mov ax, table
+        mov bx, text
+        mov cx, buffer
+        "#;
+        let program = assamble(code.to_string()).unwrap();
+        assert_eq!(&program[2..7], &[1, 2, 0x10, b'H', b'i']);
+        assert_eq!(&program[7..11], &[0x12, 0x34, 0x00, 0x02]);
+        assert_eq!(&program[11..12], &[0]);
+        assert_eq!(&program[12..17], &[b'a', b'"', b'b', b'\n', 0]);
+        assert_eq!(&program[17..20], &[255, 255, 255]);
+        // the labels point behind the data
+        assert_eq!(program[1], 20);
+        assert_eq!(&program[20..], &[0x16, 0, 2, 0x16, 1, 12, 0x16, 2, 17]);
+        let errors = assamble(".fill size\n.align 0\n.db [1]\n.dw 70000\n.bogus\n".to_string());
+        let lines: Vec<usize> = errors
+            .unwrap_err()
+            .errors
+            .iter()
+            .map(|x| x.get_span().unwrap().line)
+            .collect();
+        assert_eq!(lines, vec![1, 2, 3, 4, 5]);
+    }
+    #[test]
+    fn sections_test() {
+        use crate::assambly::{assamble_segments, Section};
+        let code = r#"
+        .data
+        message: .string "hi"
+        .bss
+        buffer: .fill 4
+        .text
+        start: mov ax, message
+        mov bx, buffer
+        int 1
+        hlt
+        .vectors
+        .org 0xFE02
+        .dw handler
+        .text
+        handler: iret
+        "#;
+        let segments = assamble_segments(code.to_string()).unwrap();
+        let found: Vec<(Section, usize, usize)> = segments
+            .iter()
+            .map(|x| (x.section, x.addres, x.bytes.len()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Section::Data, 10, 3),
+                (Section::Text, 0, 9),
+                (Section::Vectors, 0xFE02, 2),
+                (Section::Text, 9, 1),
+            ]
+        );
+        // .data follows .text and .bss follows .data
+        assert_eq!(&segments[1].bytes[..6], &[0x16, 0, 10, 0x16, 1, 13]);
+        assert_eq!(segments[2].bytes, vec![0x00, 0x09]);
+        let mut cpu = crate::devices::console_machine(
+            &segments,
+            crate::devices::console::Console::new(Box::new(std::io::sink())),
+        )
+        .unwrap();
+        cpu.run().unwrap();
+        assert_eq!(cpu.get_interrupt_vector(1), 9);
+        assert_eq!(cpu.data_bus.read_byte(11), b'i');
+        let errors =
+            assamble(".org 4\nnop\n.org 3\nnop\nnop\n.bss\nnop\n".to_string()).unwrap_err();
+        let found: Vec<(usize, &str)> = errors
+            .errors
+            .iter()
+            .map(|x| (x.get_span().unwrap().line, x.get_msg()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    4,
+                    ".text at 0x0003..0x0005 overlaps .text at 0x0004..0x0005"
+                ),
+                (7, "only .fill with 0 and .align can be used in .bss"),
+            ]
+        );
+    }
+    #[test]
+    fn expressions_test() {
+        let code = r#"
+        .equ size, 4
+        count = size * 2 + 1
+        mask = ~0x0F & 0xFF
+        mov ax, count
+        mov bx, (1 << 3) | 1 ^ 3
+        mov cx, 'A' + 1
+        mov dx, lo(table + 3)
+        add ax, [table + size]
+        add ax, [bx + size % 3 + 2]
+        mov ax, -1
+        str ax, [0x100 + hi(end)]
+        .db mask, '\n', count / 2, 7 - -1
+        .dw table - 1, hi(0x1234), end
+        .fill end - table
+        table: .db 1, 2, 3, 4, 5
+        end:
+        "#;
+        let program = assamble(code.to_string()).unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            0x16, 0, 9,          // mov ax, count
+            0x16, 1, 10,         // mov bx, (1 << 3) | 1 ^ 3
+            0x16, 2, b'B',       // mov cx, 'A' + 1
+            0x16, 3, 45,         // mov dx, lo(table + 3)
+            3, 0, 0, 46,         // add ax, [table + size]
+            4, 0, 1, 3,          // add ax, [bx + size % 3 + 2]
+            0x16, 0, 0xFF,       // mov ax, -1
+            28, 0, 0x01, 0x00,   // str ax, [0x100 + hi(end)]
+            0xF0, b'\n', 4, 8,   // .db
+            0, 41, 0, 0x12, 0, 47, // .dw
+            0, 0, 0, 0, 0,       // .fill end - table
+            1, 2, 3, 4, 5,       // table
+        ];
+        assert_eq!(program, expected);
+        let errors = assamble(
+            "mov ax, 1 / 0\nmov ax, (1\nmov ax, 300 - 44 + 1\n.equ ax, 1\nmov ax, [bx - 1]\nmov ax, 'ab'\nadd ax, [bx + 1 - 2]\n"
+                .to_string(),
+        )
+        .unwrap_err();
+        let found: Vec<(usize, &str)> = errors
+            .errors
+            .iter()
+            .map(|x| (x.get_span().unwrap().line, x.get_msg()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, "division by zero"),
+                (2, "missing ) for this ("),
+                (3, "value does not fit in a byte: 257"),
+                (4, ".equ needs a name and a value"),
+                (5, "a register can not be used in an expression: bx"),
+                (6, "a character must be one byte: 'ab'"),
+                (7, "a register offset must be from 0 to 255: -1"),
+            ]
+        );
+    }
+    #[test]
+    fn far_jumps_test() {
+        let code = r#"
+        start:
+        jmp near
+        near:
+        jne far
+        call far
+        .fill 0x100
+        far:
+        jmp start
+        hlt
+        "#;
+        let program = assamble(code.to_string()).unwrap();
+        #[rustfmt::skip]
+        let expected_start = [
+            0x27, 2,             // jmp near
+            0x35, 0x01, 0x08,    // jne far
+            0x45, 0x01, 0x08,    // call far
+        ];
+        assert_eq!(program[..8], expected_start);
+        // jmp start, hlt
+        assert_eq!(program[0x108..], [0x27, 0, 0x4C]);
+        let errors = assamble(
+            "mov ax, far
+.fill 0x100
+far: jmp 0x10000
+"
+            .to_string(),
+        )
+        .unwrap_err();
+        let found: Vec<(usize, &str)> = errors
+            .errors
+            .iter()
+            .map(|x| (x.get_span().unwrap().line, x.get_msg()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, "value does not fit in a byte: 259"),
+                (3, "addres must be valid u16: 65536"),
+            ]
+        );
+    }
 }
 
 use std::{fs, io};
+
+use assambly::{Section, Segment};
 pub fn load_bin_file(file_path: String) -> io::Result<Vec<u8>> {
-    let content = fs::read(file_path);
-    if content.is_ok() {
-        println!("{:?}", content)
-    }
-    return content;
+    fs::read(file_path)
 }
 pub fn store_bin_file(file_path: String, bin: Vec<u8>) -> Result<(), std::io::Error> {
     fs::write(file_path, bin)
 }
+/// the program at `file_path`, .asm files are assambled first and binaries are loaded at 0
+pub fn load_program(file_path: String) -> Result<Vec<Segment>, String> {
+    if file_path.ends_with(".asm") {
+        assambly::assamble_file(file_path).map_err(|err| format!("assambling error:\n{}", err))
+    } else {
+        let bytes = load_bin_file(file_path).map_err(|err| err.to_string())?;
+        Ok(vec![Segment {
+            section: Section::Text,
+            addres: 0,
+            bytes,
+        }])
+    }
+}
@@ -1,48 +1,201 @@
+use std::{
+    env,
+    io::{self, IsTerminal, Read},
+    panic,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
 use luy8_cpu::{
     assambly::{assamble, dissassamble},
     cpu::{
         memory::{DataBus, RAM},
+        throttle::Throttle,
         CPU,
     },
-    load_bin_file, store_bin_file,
+    devices::{console::Console, console_machine},
+    load_bin_file, load_program, store_bin_file,
     utils::Enumerate,
     RAM_BYTES,
 };
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(file_path) = args.first() {
+        _run_file(file_path.clone(), &args[1..]);
+        return;
+    }
     println!("Hello, world!");
     // _test_assamble();
     _test_dissassable();
     // _test_cpu();
 }
+/// ctrl-t on stdin switches turbo in a paced run, it does not reach the console
+const TURBO_KEY: u8 = 0x14;
+/// ctrl-c, read as a key because raw mode keeps the terminal from sending a signal
+const CTRL_C: u8 = 0x03;
+/// stdin without the turbo key and ctrl-c, which sets `quit`
+struct TurboKey {
+    input: io::Stdin,
+    turbo: Arc<AtomicBool>,
+    quit: Arc<AtomicBool>,
+}
+impl Read for TurboKey {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.input.read(buf)?;
+            let mut kept = 0;
+            for i in 0..read {
+                if buf[i] == TURBO_KEY {
+                    self.turbo.fetch_xor(true, Ordering::Relaxed);
+                } else if buf[i] == CTRL_C {
+                    self.quit.store(true, Ordering::Relaxed);
+                } else {
+                    buf[kept] = buf[i];
+                    kept += 1;
+                }
+            }
+            if kept > 0 || read == 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+/// keys reach a paced run right away instead of after enter, until it is
+/// dropped or the program panics
+struct RawMode;
+impl RawMode {
+    fn enable() -> Self {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            set_raw_mode(false);
+            hook(info);
+        }));
+        set_raw_mode(true);
+        RawMode
+    }
+}
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        set_raw_mode(false);
+    }
+}
+fn set_raw_mode(raw: bool) {
+    let args: &[&str] = match raw {
+        true => &["-icanon", "-isig", "min", "1"],
+        false => &["icanon", "isig"],
+    };
+    let _ = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .status();
+}
+
+/// run a .bin or .asm file with the console on stdin and stdout
+///
+/// `--hz <frequency>` runs at that emulated clock rate instead of flat out,
+/// `--turbo` starts a paced run in turbo mode, ctrl-t switches it while running
+/// and ctrl-c stops it.
+fn _run_file(file_path: String, options: &[String]) {
+    let mut frequency = None;
+    let mut turbo = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--hz" => {
+                let value = options.next().map(|x| x.as_str()).unwrap_or("");
+                match value.parse::<usize>() {
+                    Ok(hz) if hz > 0 => frequency = Some(hz),
+                    _ => {
+                        println!("--hz needs a frequency above 0, found: {:?}", value);
+                        return;
+                    }
+                }
+            }
+            "--turbo" => turbo = true,
+            _ => {
+                println!("unknown option: {}", option);
+                return;
+            }
+        }
+    }
+    let mut throttle = frequency.map(Throttle::new);
+    let quit = Arc::new(AtomicBool::new(false));
+    let input: Box<dyn Read + Send> = match &mut throttle {
+        Some(throttle) => {
+            throttle.set_turbo(turbo);
+            Box::new(TurboKey {
+                input: io::stdin(),
+                turbo: throttle.get_turbo_switch(),
+                quit: quit.clone(),
+            })
+        }
+        None => Box::new(io::stdin()),
+    };
+    let console = Console::with_input(Box::new(io::stdout()), input);
+    let program = load_program(file_path);
+    let mut cpu = match program.and_then(|program| console_machine(&program, console)) {
+        Ok(cpu) => cpu,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let res = match throttle {
+        Some(mut throttle) => {
+            throttle.on_behind = Some(Box::new(|lag| {
+                eprintln!("host fell {}ms behind", lag.as_millis())
+            }));
+            let _raw_mode = io::stdin().is_terminal().then(RawMode::enable);
+            let quit = |_: &CPU| quit.load(Ordering::Relaxed);
+            throttle.run_until(&mut cpu, quit).map(|report| {
+                eprintln!(
+                    "{} cycles in {:.3}s, fell behind {} times",
+                    report.cycles,
+                    report.elapsed.as_secs_f64(),
+                    report.times_behind
+                )
+            })
+        }
+        None => cpu.run().map(|_| ()),
+    };
+    if let Err(fault) = res {
+        println!("{}", fault);
+    }
+}
 fn _test_cpu() {
     let mut cpu = CPU::new();
     let mut data_bus = DataBus::new();
-    data_bus.add_driver(Box::new(RAM::new(RAM_BYTES * 8)));
-    println!("[{}:{}]", file!(), line!());
-    cpu.mount_data_bus(data_bus);
-    let data = load_bin_file("./data/multiply_test.bin".to_string());
-    if data.is_err() {
-        panic!("{:?}", data)
+    let res = data_bus.map_rom_file(0, "./data/multiply_test.bin".to_string());
+    if res.is_err() {
+        panic!("{:?}", res)
     }
-    let data = data.unwrap();
-    for i in 0..data.len() {
-        let byte = data[i];
-        cpu.data_bus.write_byte(i, byte);
-    }
-    for _ in 0..6 {
-        cpu.clock();
-        dbg!(&cpu.registers);
+    data_bus
+        .map(0x100, Box::new(RAM::new(RAM_BYTES * 8)))
+        .unwrap();
+    cpu.mount_data_bus(data_bus);
+    cpu.trace = Some(Box::new(std::io::stdout()));
+    match cpu.run() {
+        Ok(cycles) => println!("halted after {} cycles", cycles),
+        Err(fault) => println!("{}", fault),
     }
+    dbg!(&cpu.registers);
 }
 fn _test_assamble() {
     let byte_code = assamble(
         r#"
-    mov ax, 4
-    push ax
-    mov ax, 8
-    push ax
-    mov ax, [510]
+    mov bx, 2
+    mov cx, 3
+    mov ax, 0
+    loop:
+    add ax, bx
+    sub cx, 1
+    cmp cx, 0
+    jne loop
+    hlt
     "#
         .to_string(),
     );
@@ -13,6 +13,16 @@ impl SetGetBytes for usize {
         return ((*self >> 8 * byte) & 0xff) as u8;
     }
 }
+impl SetGetBytes for u16 {
+    fn set_byte(&mut self, byte: usize, data: u8) {
+        let mask: u16 = 0xff << (byte * 8);
+        *self = (*self & !mask) | ((data as u16) << (byte * 8));
+    }
+
+    fn get_byte(&self, byte: usize) -> u8 {
+        (*self >> (8 * byte)) as u8
+    }
+}
 pub trait Enumerate {
     type Item;
     fn enumerate(&self) -> Vec<(usize, &Self::Item)>;